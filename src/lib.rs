@@ -0,0 +1,3064 @@
+//! Shady's shader runtime: wraps tweet, Shadertoy and full GLSL snippets into
+//! fragment shaders, compiles them with glow and draws them into an egui
+//! preview or offscreen.
+//!
+//! Build a shader with [`ShaderBuilder`], then draw it with
+//! [`ShaderState::paint`] from an `egui_glow` paint callback, or read frames
+//! back with [`ShaderState::render_frame`]. [`preview::ShaderPreview`] does
+//! the drawing, compiling and input of a live preview as an egui widget.
+
+use std::fmt;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use eframe::{egui, glow};
+
+pub mod preview;
+pub mod wrapper;
+
+use wrapper::{Detection, GlslTarget, MappedSource, WrappedPass, WrappedSource};
+
+/// Name of the pass that is drawn into the preview.
+pub const IMAGE_PASS: &str = "Image";
+/// Name of the section whose code is shared by every other pass.
+pub const COMMON_PASS: &str = "Common";
+/// Name of the pass defining Shadertoy's `vec2 mainSound(int samp, float time)`.
+pub const SOUND_PASS: &str = "Sound";
+
+/// Sample rate of sound passes, as on Shadertoy.
+pub const SOUND_SAMPLE_RATE: u32 = 44100;
+/// Size of the float target that one draw of the sound pass fills, one
+/// stereo sample per pixel.
+const SOUND_BLOCK_SIZE: [i32; 2] = [512, 512];
+
+/// Clock values of one rendered frame: `iTime`, `iTimeDelta`, `iFrame`,
+/// `iFrameRate` and `iDate`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FrameClock {
+    pub time: f32,
+    pub time_delta: f32,
+    pub frame: i32,
+    pub frame_rate: f32,
+    /// Year, month (0-based), day of month and seconds since midnight.
+    pub date: [f32; 4],
+}
+
+impl FrameClock {
+    /// Clock of frame `frame` of an animation at a fixed `fps`. The date is
+    /// the Unix epoch plus the animation time, so renders are reproducible.
+    pub fn fixed(frame: u32, fps: u32) -> Self {
+        let time = frame as f32 / fps as f32;
+        Self {
+            time,
+            time_delta: 1.0 / fps as f32,
+            frame: frame as i32,
+            frame_rate: fps as f32,
+            date: [1970.0, 0.0, 1.0, time],
+        }
+    }
+}
+
+/// Mouse state over the preview, in shader pixels (origin bottom-left).
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MouseInput {
+    /// Last position while the button was held.
+    position: [f32; 2],
+    /// Where the button was last pressed.
+    click: [f32; 2],
+    down: bool,
+    /// Whether the button was pressed this frame.
+    clicked: bool,
+    /// Last hovered position, for tweet mode's `m`.
+    hover: [f32; 2],
+}
+
+impl MouseInput {
+    /// Records this frame's button state and pointer position.
+    pub fn update(&mut self, down: bool, position: Option<[f32; 2]>) {
+        self.clicked = down && !self.down;
+        self.down = down;
+        if let Some(position) = position {
+            if down {
+                self.position = position;
+            }
+            if self.clicked {
+                self.click = position;
+            }
+        }
+    }
+
+    /// Shadertoy's `iMouse`: `xy` is the drag position, `zw` the click
+    /// position, with `z` negated once the button is released and `w`
+    /// negated after the frame of the click.
+    pub fn shadertoy(&self) -> [f32; 4] {
+        let [x, y] = self.position;
+        let [cx, cy] = self.click;
+        let z = if self.down { cx } else { -cx };
+        let w = if self.clicked { cy } else { -cy };
+        [x, y, z, w]
+    }
+
+    /// Records the hovered position, for tweet mode's `m`.
+    pub fn set_hover(&mut self, position: [f32; 2]) {
+        self.hover = position;
+    }
+}
+
+/// Keys are indexed by their JavaScript key code, as on Shadertoy.
+pub const KEYBOARD_KEYS: usize = 256;
+
+/// Keyboard state in the layout of Shadertoy's 256×3 keyboard texture:
+/// row 0 is 255 while a key is down, row 1 on the frame it was pressed, and
+/// row 2 flips on every press.
+#[derive(Clone)]
+pub struct KeyboardInput {
+    texels: [u8; KEYBOARD_KEYS * 3],
+}
+
+impl Default for KeyboardInput {
+    fn default() -> Self {
+        Self {
+            texels: [0; KEYBOARD_KEYS * 3],
+        }
+    }
+}
+
+impl KeyboardInput {
+    pub fn set_key(&mut self, code: u8, down: bool) {
+        let key = code as usize;
+        let was_down = self.texels[key] != 0;
+        if down && !was_down {
+            self.texels[KEYBOARD_KEYS + key] = 255;
+            self.texels[2 * KEYBOARD_KEYS + key] ^= 255;
+        }
+        self.texels[key] = if down { 255 } else { 0 };
+    }
+
+    /// Clears the keypress row once a frame has seen it.
+    pub fn end_frame(&mut self) {
+        self.texels[KEYBOARD_KEYS..2 * KEYBOARD_KEYS].fill(0);
+    }
+
+    /// Releases every key, e.g. when the preview loses focus.
+    pub fn release_all(&mut self) {
+        self.texels[..KEYBOARD_KEYS].fill(0);
+    }
+}
+
+/// JavaScript key code of an egui key, as used by Shadertoy shaders.
+pub fn key_code(key: egui::Key) -> Option<u8> {
+    use egui::Key;
+    let code = match key {
+        Key::Backspace => 8,
+        Key::Tab => 9,
+        Key::Enter => 13,
+        Key::Escape => 27,
+        Key::Space => 32,
+        Key::PageUp => 33,
+        Key::PageDown => 34,
+        Key::End => 35,
+        Key::Home => 36,
+        Key::ArrowLeft => 37,
+        Key::ArrowUp => 38,
+        Key::ArrowRight => 39,
+        Key::ArrowDown => 40,
+        Key::Insert => 45,
+        Key::Delete => 46,
+        Key::Semicolon => 186,
+        Key::Equals | Key::Plus => 187,
+        Key::Comma => 188,
+        Key::Minus => 189,
+        Key::Period => 190,
+        Key::Slash => 191,
+        Key::Backtick => 192,
+        Key::OpenBracket => 219,
+        Key::Backslash => 220,
+        Key::CloseBracket => 221,
+        Key::Quote => 222,
+        _ => {
+            // Digits and letters use their ASCII code, F1-F12 are 112-123.
+            let name = key.name();
+            return match name.as_bytes() {
+                [c @ (b'0'..=b'9' | b'A'..=b'Z')] => Some(*c),
+                _ => name
+                    .strip_prefix('F')
+                    .and_then(|n| n.parse::<u8>().ok())
+                    .filter(|n| (1..=12).contains(n))
+                    .map(|n| 111 + n),
+            };
+        }
+    };
+    Some(code)
+}
+
+/// The local date in `iDate`'s layout.
+pub fn local_date() -> [f32; 4] {
+    use chrono::{Datelike as _, Timelike as _};
+    let now = chrono::Local::now();
+    let seconds = now.num_seconds_from_midnight() as f32 + now.nanosecond() as f32 * 1e-9;
+    [now.year() as f32, now.month0() as f32, now.day() as f32, seconds]
+}
+
+pub const CHANNEL_NAMES: [&str; 4] = ["iChannel0", "iChannel1", "iChannel2", "iChannel3"];
+
+/// Parses a `// @shady key: value` pragma line into its key and value.
+pub fn parse_pragma(line: &str) -> Option<(&str, &str)> {
+    let rest = line
+        .trim()
+        .strip_prefix("//")?
+        .trim_start()
+        .strip_prefix("@shady")?;
+    let (key, value) = rest.split_once(':')?;
+    Some((key.trim(), value.trim()))
+}
+
+/// How a snippet is wrapped into a fragment shader.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShaderMode {
+    /// A `main` body using `FC`, `r`, `t` and writing `o`.
+    Tweet,
+    /// A `mainImage` function with Shadertoy's uniforms.
+    Shadertoy,
+    /// A complete fragment shader, compiled as-is.
+    Full,
+}
+
+impl ShaderMode {
+    pub const ALL: [Self; 3] = [Self::Tweet, Self::Shadertoy, Self::Full];
+
+    /// Parses the value of a `mode` pragma or the `--mode` flag.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "tweet" => Ok(Self::Tweet),
+            "shadertoy" => Ok(Self::Shadertoy),
+            "glsl" | "full" => Ok(Self::Full),
+            _ => Err(format!(
+                "Unknown shader mode `{value}` (expected tweet, shadertoy or glsl)"
+            )),
+        }
+    }
+
+    /// Value of the `mode` pragma for this mode.
+    pub fn to_spec(self) -> &'static str {
+        match self {
+            Self::Tweet => "tweet",
+            Self::Shadertoy => "shadertoy",
+            Self::Full => "glsl",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Tweet => "Tweet",
+            Self::Shadertoy => "Shadertoy",
+            Self::Full => "Full GLSL",
+        }
+    }
+
+    /// Inputs and outputs the wrapper declares for the snippet: name,
+    /// declaration and meaning.
+    pub fn uniforms(self) -> &'static [(&'static str, &'static str, &'static str)] {
+        match self {
+            Self::Tweet => &[
+                ("FC", "vec2 FC", "Fragment coordinate in pixels, from the bottom-left."),
+                ("r", "uniform vec2 r", "Resolution of the preview in pixels."),
+                ("t", "uniform float t", "Time in seconds."),
+                ("m", "uniform vec2 m", "Mouse position over the preview, in 0..1."),
+                ("o", "vec4 o", "Output color."),
+            ],
+            Self::Shadertoy => &[
+                (
+                    "mainImage",
+                    "void mainImage(out vec4 fragColor, in vec2 fragCoord)",
+                    "Entry point, called for every pixel.",
+                ),
+                (
+                    "iResolution",
+                    "uniform vec3 iResolution",
+                    "Resolution of the preview in pixels; z is the pixel aspect ratio.",
+                ),
+                ("iTime", "uniform float iTime", "Time in seconds."),
+                ("iTimeDelta", "uniform float iTimeDelta", "Seconds since the previous frame."),
+                ("iFrameRate", "uniform float iFrameRate", "Frames per second."),
+                ("iFrame", "uniform int iFrame", "Frame number, from 0."),
+                (
+                    "iMouse",
+                    "uniform vec4 iMouse",
+                    "xy: position while the button is down; zw: click position, negated once released.",
+                ),
+                (
+                    "iDate",
+                    "uniform vec4 iDate",
+                    "Year, month (from 0), day and seconds since midnight.",
+                ),
+                (
+                    "iSampleRate",
+                    "uniform float iSampleRate",
+                    "Sample rate of the Sound pass, 44100.",
+                ),
+                (
+                    "iChannelTime",
+                    "uniform float iChannelTime[4]",
+                    "Playback time of each channel in seconds.",
+                ),
+                (
+                    "iChannelResolution",
+                    "uniform vec3 iChannelResolution[4]",
+                    "Size of each channel in pixels.",
+                ),
+                ("iChannel0", "uniform sampler2D iChannel0", "Texture of channel 0."),
+                ("iChannel1", "uniform sampler2D iChannel1", "Texture of channel 1."),
+                ("iChannel2", "uniform sampler2D iChannel2", "Texture of channel 2."),
+                ("iChannel3", "uniform sampler2D iChannel3", "Texture of channel 3."),
+                (
+                    "mainSound",
+                    "vec2 mainSound(int samp, float time)",
+                    "Entry point of the Sound pass: left and right sample at `time`.",
+                ),
+            ],
+            Self::Full => &[],
+        }
+    }
+
+    /// Short reminder of the mode's inputs for the editor header.
+    pub fn hint(self) -> &'static str {
+        match self {
+            Self::Tweet => "o: vec4 • FC, r, t, m",
+            Self::Shadertoy => "mainImage • iTime, iResolution, iMouse",
+            Self::Full => "void main() • gl_FragCoord",
+        }
+    }
+}
+
+/// The mode forced by a `// @shady mode: <mode>` line, if any.
+pub fn mode_pragma(snippet: &str) -> Result<Option<ShaderMode>, String> {
+    snippet
+        .lines()
+        .find_map(|line| match parse_pragma(line) {
+            Some(("mode", value)) => Some(ShaderMode::parse(value)),
+            _ => None,
+        })
+        .transpose()
+}
+
+/// Returns `snippet` with its `mode` pragma set to `mode`, or removed for
+/// `None`. A new pragma goes on the first line, after any `#version`.
+pub fn set_mode_pragma(snippet: &str, mode: Option<ShaderMode>) -> String {
+    let mut lines: Vec<String> = snippet.lines().map(str::to_owned).collect();
+    let existing = lines
+        .iter()
+        .position(|l| matches!(parse_pragma(l), Some(("mode", _))));
+    match (existing, mode) {
+        (Some(i), Some(mode)) => lines[i] = format!("// @shady mode: {}", mode.to_spec()),
+        (Some(i), None) => {
+            lines.remove(i);
+        }
+        (None, Some(mode)) => {
+            let at = usize::from(lines.first().is_some_and(|l| l.trim_start().starts_with("#version")));
+            lines.insert(at, format!("// @shady mode: {}", mode.to_spec()));
+        }
+        (None, None) => {}
+    }
+
+    let mut out = lines.join("\n");
+    if snippet.ends_with('\n') {
+        out.push('\n');
+    }
+    out
+}
+
+/// What an `iChannelN` sampler of a pass is bound to.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum ChannelSource {
+    /// The built-in procedural noise texture.
+    #[default]
+    Noise,
+    /// The output of a pass, by name. Passes that already ran this frame give
+    /// their current output, the pass itself and later passes give the
+    /// previous frame.
+    Buffer(String),
+    /// A PNG or JPEG file. Relative paths are resolved against
+    /// [`ShaderOptions::base_dir`].
+    Image(PathBuf),
+    /// An animated GIF, played in a loop in sync with `iTime`.
+    Video(PathBuf),
+    /// A WAV file, exposed as Shadertoy's 512×2 spectrum/waveform texture.
+    Audio(PathBuf),
+    /// Shadertoy's 256×3 keyboard texture, fed by keys pressed while the
+    /// preview has focus.
+    Keyboard,
+}
+
+impl ChannelSource {
+    /// Parses the value of an `iChannelN` pragma. `pass_name` is the pass the
+    /// pragma belongs to, so that `self` can be resolved.
+    pub fn parse(spec: &str, pass_name: &str) -> Result<Self, String> {
+        let (kind, arg) = match spec.split_once(char::is_whitespace) {
+            Some((kind, arg)) => (kind, arg.trim()),
+            None => (spec, ""),
+        };
+        match kind.to_ascii_lowercase().as_str() {
+            "noise" => Ok(Self::Noise),
+            "self" => Ok(Self::Buffer(pass_name.to_owned())),
+            "buffer" if !arg.is_empty() => Ok(Self::Buffer(arg.to_owned())),
+            "buffer" => Err("`buffer` channel needs a pass name".to_owned()),
+            "image" if !arg.is_empty() => Ok(Self::Image(PathBuf::from(unquote(arg)))),
+            "image" => Err("`image` channel needs a file path".to_owned()),
+            "video" if !arg.is_empty() => Ok(Self::Video(PathBuf::from(unquote(arg)))),
+            "video" => Err("`video` channel needs a file path".to_owned()),
+            "audio" if !arg.is_empty() => Ok(Self::Audio(PathBuf::from(unquote(arg)))),
+            "audio" => Err("`audio` channel needs a file path".to_owned()),
+            "keyboard" => Ok(Self::Keyboard),
+            _ => Err(format!("Unknown channel source `{spec}`")),
+        }
+    }
+
+    /// Picks the channel kind for a file from its extension.
+    pub fn from_file(path: PathBuf) -> Self {
+        let extension = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("gif") => Self::Video(path),
+            Some("wav") => Self::Audio(path),
+            _ => Self::Image(path),
+        }
+    }
+
+    /// Formats the source back into the value of an `iChannelN` pragma.
+    pub fn to_spec(&self) -> String {
+        match self {
+            Self::Noise => "noise".to_owned(),
+            Self::Buffer(name) => format!("buffer {name}"),
+            Self::Image(path) => format!("image {}", quote_path(path)),
+            Self::Video(path) => format!("video {}", quote_path(path)),
+            Self::Audio(path) => format!("audio {}", quote_path(path)),
+            Self::Keyboard => "keyboard".to_owned(),
+        }
+    }
+
+    /// Short label for the channel slots in the UI.
+    pub fn label(&self) -> String {
+        match self {
+            Self::Noise => "noise".to_owned(),
+            Self::Keyboard => "keyboard".to_owned(),
+            Self::Buffer(name) => name.clone(),
+            Self::Image(path) | Self::Video(path) | Self::Audio(path) => path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_else(|| path.display().to_string()),
+        }
+    }
+}
+
+/// Texture filtering of a channel. `Mipmap` is trilinear filtering, which is
+/// what `textureLod` and distant lookups need.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextureFilter {
+    Nearest,
+    Linear,
+    Mipmap,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextureWrap {
+    Clamp,
+    Repeat,
+}
+
+/// How a channel is sampled, resolved from its pragma options and the
+/// defaults of its source kind.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SamplerSettings {
+    pub filter: TextureFilter,
+    pub wrap: TextureWrap,
+    /// Flip image rows at load time so `uv.y = 0` is the bottom of the image.
+    pub vflip: bool,
+}
+
+/// An `iChannelN` pragma: the source plus the sampler options that were
+/// spelled out, e.g. `image wood.png filter=nearest wrap=clamp vflip=false`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ChannelConfig {
+    pub source: ChannelSource,
+    pub filter: Option<TextureFilter>,
+    pub wrap: Option<TextureWrap>,
+    pub vflip: Option<bool>,
+}
+
+impl ChannelConfig {
+    /// Parses the value of an `iChannelN` pragma, source and options.
+    pub fn parse(spec: &str, pass_name: &str) -> Result<Self, String> {
+        let mut config = Self::default();
+        let mut source_words: Vec<&str> = Vec::new();
+
+        for word in split_words(spec) {
+            let option = if word.starts_with('"') {
+                None
+            } else if word == "vflip" {
+                Some(("vflip", "true"))
+            } else {
+                word.split_once('=')
+            };
+            match option {
+                Some(("filter", value)) => {
+                    config.filter = Some(match value {
+                        "nearest" => TextureFilter::Nearest,
+                        "linear" => TextureFilter::Linear,
+                        "mipmap" => TextureFilter::Mipmap,
+                        _ => return Err(format!("Unknown filter `{value}`")),
+                    });
+                }
+                Some(("wrap", value)) => {
+                    config.wrap = Some(match value {
+                        "clamp" => TextureWrap::Clamp,
+                        "repeat" => TextureWrap::Repeat,
+                        _ => return Err(format!("Unknown wrap mode `{value}`")),
+                    });
+                }
+                Some(("vflip", value)) => {
+                    config.vflip = Some(match value {
+                        "true" => true,
+                        "false" => false,
+                        _ => return Err(format!("`vflip` must be true or false, not `{value}`")),
+                    });
+                }
+                Some((key, _)) => return Err(format!("Unknown channel option `{key}`")),
+                None => source_words.push(word),
+            }
+        }
+
+        config.source = ChannelSource::parse(&source_words.join(" "), pass_name)?;
+        Ok(config)
+    }
+
+    pub fn to_spec(&self) -> String {
+        let mut spec = self.source.to_spec();
+        if let Some(filter) = self.filter {
+            spec.push_str(match filter {
+                TextureFilter::Nearest => " filter=nearest",
+                TextureFilter::Linear => " filter=linear",
+                TextureFilter::Mipmap => " filter=mipmap",
+            });
+        }
+        if let Some(wrap) = self.wrap {
+            spec.push_str(match wrap {
+                TextureWrap::Clamp => " wrap=clamp",
+                TextureWrap::Repeat => " wrap=repeat",
+            });
+        }
+        if let Some(vflip) = self.vflip {
+            spec.push_str(if vflip { " vflip=true" } else { " vflip=false" });
+        }
+        spec
+    }
+
+    /// Fills in the options that weren't given with Shadertoy's defaults for
+    /// the source: textures are mipmapped, repeating and flipped, buffers
+    /// are linear and clamped. Noise keeps its nearest, repeating look.
+    pub fn sampler(&self) -> SamplerSettings {
+        let (filter, wrap) = match self.source {
+            ChannelSource::Noise => (TextureFilter::Nearest, TextureWrap::Repeat),
+            ChannelSource::Keyboard => (TextureFilter::Nearest, TextureWrap::Clamp),
+            ChannelSource::Buffer(_) => (TextureFilter::Linear, TextureWrap::Clamp),
+            ChannelSource::Image(_) => (TextureFilter::Mipmap, TextureWrap::Repeat),
+            ChannelSource::Video(_) | ChannelSource::Audio(_) => {
+                (TextureFilter::Linear, TextureWrap::Clamp)
+            }
+        };
+        SamplerSettings {
+            filter: self.filter.unwrap_or(filter),
+            wrap: self.wrap.unwrap_or(wrap),
+            vflip: self.vflip.unwrap_or(true),
+        }
+    }
+}
+
+/// Splits on whitespace, keeping `"quoted words"` (quotes included) together.
+fn split_words(s: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    let mut rest = s.trim_start();
+    while !rest.is_empty() {
+        let end = if let Some(quoted) = rest.strip_prefix('"') {
+            quoted.find('"').map_or(rest.len(), |i| i + 2)
+        } else {
+            rest.find(char::is_whitespace).unwrap_or(rest.len())
+        };
+        words.push(&rest[..end]);
+        rest = rest[end..].trim_start();
+    }
+    words
+}
+
+/// Strips one pair of surrounding double quotes, used for paths with spaces.
+fn unquote(s: &str) -> &str {
+    s.strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .unwrap_or(s)
+}
+
+fn quote_path(path: &Path) -> String {
+    let path = path.display().to_string();
+    if path.contains(char::is_whitespace) {
+        format!("\"{path}\"")
+    } else {
+        path
+    }
+}
+
+/// Compile-time inputs of a shader besides its source.
+#[derive(Clone, Default)]
+pub struct ShaderOptions {
+    /// Directory that relative channel paths are resolved against, usually
+    /// the directory of the open shader file.
+    pub base_dir: Option<PathBuf>,
+    /// Channel sources that replace the Image pass's `iChannelN` pragmas,
+    /// set from the command line.
+    pub channel_overrides: [Option<ChannelConfig>; 4],
+    /// Mode that replaces the snippet's `mode` pragma, set from the command
+    /// line.
+    pub mode: Option<ShaderMode>,
+    /// Noise texture to bind to unassigned channels, shared by every compile
+    /// of the app. Without one, each shader creates and deletes its own.
+    pub noise_texture: Option<glow::Texture>,
+    /// Extra uniforms with their initial values, declared for tweet and
+    /// Shadertoy code. Full shaders have to declare them themselves.
+    pub uniforms: Vec<(String, Uniform)>,
+}
+
+impl ShaderOptions {
+    fn resolve_path(&self, path: &Path) -> PathBuf {
+        match &self.base_dir {
+            Some(dir) if path.is_relative() => dir.join(path),
+            _ => path.to_owned(),
+        }
+    }
+
+    /// Declarations of the extra uniforms, one line each.
+    fn uniform_declarations(&self) -> String {
+        self.uniforms
+            .iter()
+            .map(|(name, value)| format!("uniform {} {name};\n", value.glsl_type()))
+            .collect()
+    }
+}
+
+/// Value of an extra uniform, set by the embedding app.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Uniform {
+    Float(f32),
+    Vec2([f32; 2]),
+    Vec3([f32; 3]),
+    Vec4([f32; 4]),
+    Int(i32),
+}
+
+impl Uniform {
+    pub fn glsl_type(self) -> &'static str {
+        match self {
+            Uniform::Float(_) => "float",
+            Uniform::Vec2(_) => "vec2",
+            Uniform::Vec3(_) => "vec3",
+            Uniform::Vec4(_) => "vec4",
+            Uniform::Int(_) => "int",
+        }
+    }
+
+    unsafe fn apply(self, gl: &glow::Context, location: &glow::UniformLocation) {
+        use glow::HasContext as _;
+        let location = Some(location);
+        match self {
+            Uniform::Float(x) => gl.uniform_1_f32(location, x),
+            Uniform::Vec2([x, y]) => gl.uniform_2_f32(location, x, y),
+            Uniform::Vec3([x, y, z]) => gl.uniform_3_f32(location, x, y, z),
+            Uniform::Vec4([x, y, z, w]) => gl.uniform_4_f32(location, x, y, z, w),
+            Uniform::Int(x) => gl.uniform_1_i32(location, x),
+        }
+    }
+}
+
+/// Sets up a shader from a snippet: its mode, channels and extra uniforms.
+///
+/// ```no_run
+/// # fn build(gl: &eframe::glow::Context) -> Result<(), shady::ShaderError> {
+/// use shady::{ShaderBuilder, ShaderMode, Uniform};
+///
+/// let mut shader = ShaderBuilder::new("o = vec4(tint * (0.5 + 0.5 * sin(t)), 1);")
+///     .mode(ShaderMode::Tweet)
+///     .uniform("tint", Uniform::Vec3([1.0, 0.5, 0.2]))
+///     .build(gl)?;
+/// let rgba = shader.render_frame(gl, 1.0, 30, [256, 256])?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct ShaderBuilder {
+    snippet: String,
+    options: ShaderOptions,
+}
+
+impl ShaderBuilder {
+    pub fn new(snippet: impl Into<String>) -> Self {
+        Self {
+            snippet: snippet.into(),
+            options: ShaderOptions::default(),
+        }
+    }
+
+    /// Compiles in `mode` only, instead of detecting it.
+    pub fn mode(mut self, mode: ShaderMode) -> Self {
+        self.options.mode = Some(mode);
+        self
+    }
+
+    /// Binds `config` to `iChannel<index>` of the Image pass, replacing the
+    /// snippet's pragma.
+    ///
+    /// # Panics
+    ///
+    /// If `index` is 4 or more.
+    pub fn channel(mut self, index: usize, config: ChannelConfig) -> Self {
+        assert!(index < CHANNEL_NAMES.len(), "no iChannel{index}, only 0 to 3");
+        self.options.channel_overrides[index] = Some(config);
+        self
+    }
+
+    /// Binds an image, GIF or audio file to `iChannel<index>`, by extension.
+    ///
+    /// # Panics
+    ///
+    /// If `index` is 4 or more.
+    pub fn channel_file(self, index: usize, path: impl Into<PathBuf>) -> Self {
+        let config = ChannelConfig {
+            source: ChannelSource::from_file(path.into()),
+            ..Default::default()
+        };
+        self.channel(index, config)
+    }
+
+    /// Directory that relative channel paths are resolved against.
+    pub fn base_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.options.base_dir = Some(dir.into());
+        self
+    }
+
+    /// Shares a noise texture from `create_noise_texture` between shaders.
+    pub fn noise_texture(mut self, texture: glow::Texture) -> Self {
+        self.options.noise_texture = Some(texture);
+        self
+    }
+
+    /// Declares an extra uniform, updated later with
+    /// `ShaderState::set_uniform`.
+    pub fn uniform(mut self, name: impl Into<String>, value: Uniform) -> Self {
+        let name = name.into();
+        self.options.uniforms.retain(|(n, _)| *n != name);
+        self.options.uniforms.push((name, value));
+        self
+    }
+
+    /// Compiles the shader, blocking until the driver is done.
+    pub fn build(&self, gl: &glow::Context) -> Result<ShaderState, ShaderError> {
+        ShaderState::new(gl, &self.snippet, &self.options)
+    }
+
+    /// Starts compiling the shader; see `ShaderState::start`.
+    pub fn start(&self, gl: &glow::Context, parallel: bool) -> Result<PendingShader, ShaderError> {
+        ShaderState::start(gl, &self.snippet, &self.options, parallel)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Warning => "warning",
+        }
+    }
+}
+
+/// One message of a driver's compile log, with its position mapped back to
+/// the snippet.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    /// 1-based snippet line, or `None` for messages about Shady's own
+    /// wrapper code.
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.line, self.column) {
+            (Some(line), Some(column)) => write!(f, "{line}:{column}: ")?,
+            (Some(line), None) => write!(f, "{line}: ")?,
+            (None, _) => write!(f, "wrapper: ")?,
+        }
+        write!(f, "{}: {}", self.severity.name(), self.message)
+    }
+}
+
+/// Parses one line of a driver's info log into its source line, column,
+/// severity and message. Understands the Mesa (`0:12(5): error: ...`),
+/// NVIDIA (`0(12) : error C1008: ...`) and ANGLE/AMD/Apple
+/// (`ERROR: 0:12: ...`) formats.
+fn parse_log_line(line: &str) -> Option<(usize, Option<usize>, Severity, String)> {
+    // Also matches qualified words like Mesa's `preprocessor error`.
+    let severity_of = |word: &str| {
+        let word = word.trim().to_ascii_lowercase();
+        if word.ends_with("error") {
+            Some(Severity::Error)
+        } else if word.ends_with("warning") {
+            Some(Severity::Warning)
+        } else {
+            None
+        }
+    };
+    let line = line.trim();
+
+    // ANGLE / AMD / Apple: `ERROR: 0:12: message`
+    if let Some((word, rest)) = line.split_once(':') {
+        let upper = word.chars().all(|c| c.is_ascii_uppercase());
+        if let Some(severity) = severity_of(word).filter(|_| upper) {
+            let mut parts = rest.trim_start().splitn(3, ':');
+            parts.next()?.parse::<u32>().ok()?;
+            let number = parts.next()?.trim().parse().ok()?;
+            let message = parts.next().unwrap_or_default().trim().to_owned();
+            return Some((number, None, severity, message));
+        }
+    }
+
+    let file_end = line.find(|c: char| !c.is_ascii_digit())?;
+    if file_end == 0 {
+        return None;
+    }
+    let rest = &line[file_end..];
+    if let Some(rest) = rest.strip_prefix(':') {
+        // Mesa: `0:12(5): error: message`
+        let (number, rest) = rest.split_once('(')?;
+        let (column, rest) = rest.split_once(')')?;
+        let (word, message) = rest.strip_prefix(':')?.split_once(':')?;
+        Some((
+            number.parse().ok()?,
+            column.parse().ok(),
+            severity_of(word)?,
+            message.trim().to_owned(),
+        ))
+    } else {
+        // NVIDIA: `0(12) : error C1008: message`
+        let (number, rest) = rest.strip_prefix('(')?.split_once(')')?;
+        let (word, message) = rest.trim_start().strip_prefix(':')?.split_once(':')?;
+        let word = word.split_whitespace().next()?;
+        Some((number.parse().ok()?, None, severity_of(word)?, message.trim().to_owned()))
+    }
+}
+
+/// Rewrites the line numbers of a compile log with `map`, which turns a line
+/// of the compiled source into a snippet line. Returns the rewritten log and
+/// its diagnostics; lines that aren't diagnostics are kept as they are.
+fn remap_log(log: &str, map: impl Fn(usize) -> Option<usize>) -> (String, Vec<Diagnostic>) {
+    let mut text = Vec::new();
+    let mut diagnostics = Vec::new();
+    for line in log.lines() {
+        match parse_log_line(line) {
+            Some((number, column, severity, message)) => {
+                let diagnostic = Diagnostic {
+                    line: map(number),
+                    column,
+                    severity,
+                    message,
+                };
+                text.push(diagnostic.to_string());
+                diagnostics.push(diagnostic);
+            }
+            None => text.push(line.to_owned()),
+        }
+    }
+    (text.join("\n"), diagnostics)
+}
+
+/// How far a failed compile got, for picking the mode whose error to
+/// report: failing to link beats failing to compile, parsing beats a syntax
+/// error, and a first error further into the snippet beats an earlier one.
+/// `header_lines` is the number of wrapper lines before the snippet.
+fn compile_progress(err: &str, header_lines: usize) -> (bool, bool, i64) {
+    let linked = err.starts_with(LINK_ERROR);
+    let parsed = !err.contains("syntax error");
+    let header_lines = header_lines as i64;
+    let first_error = err
+        .lines()
+        .filter_map(parse_log_line)
+        .filter(|(_, _, severity, _)| *severity == Severity::Error)
+        .map(|(line, ..)| line as i64 - header_lines)
+        .min()
+        .unwrap_or(0);
+    (linked, parsed, first_error)
+}
+
+/// Error of a pass no mode compiled: that of the mode that got furthest, with
+/// the others folded in. Ties go to the likelier mode.
+fn mode_error(mut failures: Vec<ModeFailure>) -> ShaderError {
+    let mut best = 0;
+    for (index, failure) in failures.iter().enumerate() {
+        if failure.progress > failures[best].progress {
+            best = index;
+        }
+    }
+    let failure = failures.remove(best);
+    ShaderError {
+        message: format!("{} mode failed:\n{}", failure.mode.label(), failure.log),
+        pass: None,
+        mode: Some(failure.mode),
+        diagnostics: failure.diagnostics,
+        other_modes: failures
+            .into_iter()
+            .map(|failure| (failure.mode, failure.log))
+            .collect(),
+    }
+}
+
+/// Why a shader failed to build: the message shown to the user, and the
+/// diagnostics parsed from the driver's log, if any.
+#[derive(Debug)]
+pub struct ShaderError {
+    pub message: String,
+    /// Pass the error is in, for shaders with several passes. The message
+    /// starts with it.
+    pub pass: Option<String>,
+    /// Mode the message and diagnostics are about, for compile failures.
+    pub mode: Option<ShaderMode>,
+    pub diagnostics: Vec<Diagnostic>,
+    /// Logs of the modes that were tried but aren't the likely intent,
+    /// shown folded away.
+    pub other_modes: Vec<(ShaderMode, String)>,
+}
+
+impl ShaderError {
+    /// Marks the error as being in pass `name`, starting the message with a
+    /// `<name> pass:` line.
+    pub fn in_pass(self, name: &str) -> Self {
+        Self {
+            message: format!("{name} pass:\n{}", self.message),
+            pass: Some(name.to_owned()),
+            ..self
+        }
+    }
+}
+
+impl From<String> for ShaderError {
+    fn from(message: String) -> Self {
+        Self {
+            message,
+            pass: None,
+            mode: None,
+            diagnostics: Vec::new(),
+            other_modes: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)?;
+        if !self.other_modes.is_empty() {
+            let modes: Vec<&str> = self.other_modes.iter().map(|(mode, _)| mode.label()).collect();
+            write!(f, "\n\n({} mode also failed)", modes.join(" and "))?;
+        }
+        Ok(())
+    }
+}
+
+/// One section of the snippet, delimited by `// @shady pass: <name>` lines.
+pub struct PassSource {
+    pub name: String,
+    pub source: MappedSource,
+    pub channels: [ChannelConfig; 4],
+}
+
+/// Splits a snippet into the shared common code and its render passes.
+///
+/// A snippet without any `pass` pragma is a single Image pass. Otherwise the
+/// text before the first pragma, together with an optional `Common` pass, is
+/// prepended to every pass, the way Shadertoy's Common tab is.
+pub fn split_passes(snippet: &str) -> Result<(MappedSource, Vec<PassSource>), String> {
+    let mut common = MappedSource::default();
+    let mut passes: Vec<PassSource> = Vec::new();
+    let mut current: Option<PassSource> = None;
+    let has_passes = snippet
+        .lines()
+        .any(|line| matches!(parse_pragma(line), Some(("pass", _))));
+
+    if !has_passes {
+        current = Some(PassSource {
+            name: IMAGE_PASS.to_owned(),
+            source: MappedSource::default(),
+            channels: Default::default(),
+        });
+    }
+
+    for (index, line) in snippet.lines().enumerate() {
+        match parse_pragma(line) {
+            Some(("pass", name)) => {
+                if name.is_empty() {
+                    return Err("`pass` pragma needs a name".to_owned());
+                }
+                if let Some(pass) = current.take() {
+                    passes.push(pass);
+                }
+                let name = if name.eq_ignore_ascii_case(IMAGE_PASS) {
+                    IMAGE_PASS
+                } else if name.eq_ignore_ascii_case(COMMON_PASS) {
+                    COMMON_PASS
+                } else if name.eq_ignore_ascii_case(SOUND_PASS) {
+                    SOUND_PASS
+                } else {
+                    name
+                };
+                if passes.iter().any(|p| p.name.eq_ignore_ascii_case(name)) {
+                    return Err(format!("Pass `{name}` is defined twice"));
+                }
+                current = Some(PassSource {
+                    name: name.to_owned(),
+                    source: MappedSource::default(),
+                    channels: Default::default(),
+                });
+            }
+            Some((key, value)) if key.starts_with("iChannel") => {
+                let index = CHANNEL_NAMES
+                    .iter()
+                    .position(|name| *name == key)
+                    .ok_or_else(|| format!("Unknown channel `{key}`"))?;
+                match current.as_mut() {
+                    Some(pass) if pass.name != COMMON_PASS => {
+                        pass.channels[index] = ChannelConfig::parse(value, &pass.name)?;
+                    }
+                    _ => return Err(format!("`{key}` pragma must follow a pass pragma")),
+                }
+            }
+            _ => {}
+        }
+
+        // Pragma lines are GLSL comments, so they are kept in the source.
+        match current.as_mut() {
+            Some(pass) => pass.source.push_line(line, index + 1),
+            None => common.push_line(line, index + 1),
+        }
+    }
+    if let Some(pass) = current.take() {
+        passes.push(pass);
+    }
+
+    if let Some(index) = passes.iter().position(|p| p.name == COMMON_PASS) {
+        common.append(&passes.remove(index).source);
+    }
+    if !passes.iter().any(|p| p.name == IMAGE_PASS) {
+        return Err("Snippet has passes but no `Image` pass".to_owned());
+    }
+
+    Ok((common, passes))
+}
+
+/// Returns `snippet` with the Image pass's `iChannelN` pragma set to `config`.
+/// An existing pragma is replaced, otherwise one is inserted at the start of
+/// the pass. Setting a channel back to plain noise removes its pragma.
+///
+/// # Panics
+///
+/// If `channel` is 4 or more.
+pub fn set_channel_pragma(snippet: &str, channel: usize, config: &ChannelConfig) -> String {
+    assert!(channel < CHANNEL_NAMES.len(), "no iChannel{channel}, only 0 to 3");
+    let key = CHANNEL_NAMES[channel];
+    let is_pass_pragma = |line: &str| matches!(parse_pragma(line), Some(("pass", _)));
+    let mut lines: Vec<String> = snippet.lines().map(str::to_owned).collect();
+
+    // Lines that belong to the Image pass.
+    let (start, end) = if lines.iter().any(|l| is_pass_pragma(l)) {
+        let Some(marker) = lines.iter().position(|l| {
+            matches!(parse_pragma(l), Some(("pass", name)) if name.eq_ignore_ascii_case(IMAGE_PASS))
+        }) else {
+            return snippet.to_owned();
+        };
+        let end = lines[marker + 1..]
+            .iter()
+            .position(|l| is_pass_pragma(l))
+            .map_or(lines.len(), |p| marker + 1 + p);
+        (marker + 1, end)
+    } else {
+        (0, lines.len())
+    };
+
+    let existing = (start..end).find(|&i| matches!(parse_pragma(&lines[i]), Some((k, _)) if k == key));
+    let pragma = format!("// @shady {key}: {}", config.to_spec());
+    let is_default = *config == ChannelConfig::default();
+    match existing {
+        Some(i) if is_default => {
+            lines.remove(i);
+        }
+        Some(i) => lines[i] = pragma,
+        None if is_default => {}
+        None => {
+            // Keep channel pragmas grouped at the top of the pass.
+            let mut at = start;
+            while at < end
+                && matches!(parse_pragma(&lines[at]), Some((k, _)) if k.starts_with("iChannel"))
+            {
+                at += 1;
+            }
+            lines.insert(at, pragma);
+        }
+    }
+
+    let mut out = lines.join("\n");
+    if snippet.ends_with('\n') {
+        out.push('\n');
+    }
+    out
+}
+
+/// A channel after pass names have been resolved and files loaded.
+#[derive(Clone, Copy)]
+enum ChannelBinding {
+    Noise,
+    Buffer(usize),
+    /// Index into [`ShaderState::channel_textures`].
+    Texture(usize),
+}
+
+/// A texture loaded from disk for one channel.
+struct ChannelTexture {
+    texture: glow::Texture,
+    size: [u32; 2],
+    /// Video or audio data, re-uploaded as `iTime` advances.
+    stream: Option<ChannelStream>,
+    /// Playback position reported in `iChannelTime`.
+    time: f32,
+}
+
+enum ChannelStream {
+    Video(VideoFrames),
+    Audio(AudioTrack),
+    /// Uploaded from the [`KeyboardInput`] passed to `paint`.
+    Keyboard,
+}
+
+/// Decoded frames of an animated GIF channel.
+struct VideoFrames {
+    /// Full RGBA canvas of each frame, after GIF disposal is applied.
+    frames: Vec<Vec<u8>>,
+    /// Time in seconds at which each frame ends, from the start of the loop.
+    frame_ends: Vec<f32>,
+    /// Frame currently in the texture.
+    current: usize,
+}
+
+impl VideoFrames {
+    /// Returns the frame to show at `time` and the position within the loop.
+    fn frame_at(&self, time: f32) -> (usize, f32) {
+        let duration = self.frame_ends.last().copied().unwrap_or(0.0);
+        if duration <= 0.0 {
+            return (0, 0.0);
+        }
+        let position = time.max(0.0) % duration;
+        let frame = self
+            .frame_ends
+            .partition_point(|&end| end <= position)
+            .min(self.frames.len() - 1);
+        (frame, position)
+    }
+}
+
+/// Width of the audio texture: 512 spectrum bins and 512 waveform samples.
+const AUDIO_TEXTURE_WIDTH: usize = 512;
+/// Samples analysed per frame, as WebAudio's `fftSize` on Shadertoy.
+const AUDIO_FFT_SIZE: usize = 2 * AUDIO_TEXTURE_WIDTH;
+
+/// Mono samples of an audio channel.
+struct AudioTrack {
+    samples: Vec<f32>,
+    sample_rate: u32,
+    /// Sample index the texture was last computed at.
+    current: Option<usize>,
+}
+
+impl AudioTrack {
+    /// Returns the sample index that `time` falls on and the position in
+    /// seconds within the loop.
+    fn sample_at(&self, time: f32) -> (usize, f32) {
+        let duration = self.samples.len() as f32 / self.sample_rate as f32;
+        if duration <= 0.0 {
+            return (0, 0.0);
+        }
+        let position = time.max(0.0) % duration;
+        let index = ((position * self.sample_rate as f32) as usize).min(self.samples.len() - 1);
+        (index, position)
+    }
+
+    /// Builds the 512×2 single channel texture Shadertoy exposes for music:
+    /// row 0 is the spectrum of the last 1024 samples, row 1 the waveform.
+    /// Like WebAudio's analyser, the spectrum uses a Blackman window and maps
+    /// -100..-30 dB to 0..255, but without smoothing between frames, so the
+    /// texture only depends on the time.
+    fn texture_data(&self, index: usize) -> Vec<u8> {
+        let mut re = vec![0.0f32; AUDIO_FFT_SIZE];
+        let mut im = vec![0.0f32; AUDIO_FFT_SIZE];
+        for (i, value) in re.iter_mut().enumerate() {
+            let sample = (index + i + 1)
+                .checked_sub(AUDIO_FFT_SIZE)
+                .map_or(0.0, |s| self.samples[s]);
+            let x = i as f32 / AUDIO_FFT_SIZE as f32;
+            let window = 0.42 - 0.5 * (std::f32::consts::TAU * x).cos()
+                + 0.08 * (2.0 * std::f32::consts::TAU * x).cos();
+            *value = sample * window;
+        }
+
+        let mut data = vec![0u8; AUDIO_TEXTURE_WIDTH * 2];
+        // Waveform first, from the most recent half of the window.
+        for (i, value) in data[AUDIO_TEXTURE_WIDTH..].iter_mut().enumerate() {
+            let sample = (index + i + 1)
+                .checked_sub(AUDIO_TEXTURE_WIDTH)
+                .map_or(0.0, |s| self.samples[s]);
+            *value = (128.0 + 128.0 * sample).clamp(0.0, 255.0) as u8;
+        }
+
+        fft(&mut re, &mut im);
+        for (bin, value) in data[..AUDIO_TEXTURE_WIDTH].iter_mut().enumerate() {
+            let magnitude = (re[bin] * re[bin] + im[bin] * im[bin]).sqrt() / AUDIO_FFT_SIZE as f32;
+            let db = 20.0 * magnitude.max(1e-10).log10();
+            *value = (255.0 * (db + 100.0) / 70.0).clamp(0.0, 255.0) as u8;
+        }
+
+        data
+    }
+}
+
+/// In-place radix-2 FFT. Both slices must have the same power of two length.
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let angle = -std::f32::consts::TAU / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let t_re = re[b] * cos - im[b] * sin;
+                let t_im = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+/// Double-buffered offscreen target of a buffer pass.
+struct PassTarget {
+    framebuffers: [glow::Framebuffer; 2],
+    textures: [glow::Texture; 2],
+    size: [i32; 2],
+    /// Index of the texture holding the most recent output.
+    front: usize,
+}
+
+struct RenderPass {
+    name: String,
+    program: glow::Program,
+    /// Mode the pass compiled in.
+    mode: ShaderMode,
+    /// Why its modes were tried in the order they were.
+    detection: Detection,
+    /// Logs of the modes tried before the one it compiled in.
+    failed_modes: Vec<(ShaderMode, String)>,
+    channels: [ChannelBinding; 4],
+    /// Sampler objects bound alongside each channel's texture.
+    samplers: [glow::Sampler; 4],
+    /// Channels that sample a buffer with mipmaps, whose mip chain has to be
+    /// rebuilt every frame.
+    mipmapped: [bool; 4],
+    /// `None` for the Image pass, which draws straight into the preview.
+    target: Option<PassTarget>,
+}
+
+/// A program whose shaders were compiled and linked without waiting for
+/// the result.
+struct ProgramJob {
+    program: glow::Program,
+    shaders: [glow::Shader; 2],
+}
+
+/// A pass's code wrapped in one of the modes it may be in.
+struct ModeAttempt {
+    wrapped: WrappedSource,
+    /// Program submitted to the driver, or the error submitting it.
+    job: Option<Result<ProgramJob, String>>,
+}
+
+/// Error of a mode that didn't compile.
+struct ModeFailure {
+    mode: ShaderMode,
+    /// `compile_progress` of the error.
+    progress: (bool, bool, i64),
+    log: String,
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// The modes one pass may be in, in the order they're tried.
+struct ProgramBuild {
+    detection: Detection,
+    attempts: Vec<ModeAttempt>,
+}
+
+impl ProgramBuild {
+    fn new(pass: WrappedPass) -> Self {
+        Self {
+            detection: pass.detection,
+            attempts: pass
+                .sources
+                .into_iter()
+                .map(|wrapped| ModeAttempt { wrapped, job: None })
+                .collect(),
+        }
+    }
+
+    /// Submits every mode to the driver at once.
+    unsafe fn submit(&mut self, gl: &glow::Context, vertex_shader_source: &str) {
+        for attempt in &mut self.attempts {
+            if attempt.job.is_none() {
+                let job = submit_program(gl, vertex_shader_source, &attempt.wrapped.source);
+                attempt.job = Some(job);
+            }
+        }
+    }
+
+    /// Whether `finish` can pick a mode without waiting for the driver.
+    unsafe fn is_ready(&self, gl: &glow::Context) -> bool {
+        use glow::HasContext as _;
+        for attempt in &self.attempts {
+            match &attempt.job {
+                // Compiled by `finish` itself
+                None => return true,
+                Some(Err(_)) => continue,
+                Some(Ok(job)) => {
+                    if !gl.get_program_completion_status(job.program) {
+                        return false;
+                    }
+                    if gl.get_program_link_status(job.program) {
+                        return true;
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    /// Takes the first mode that links, compiling those that weren't
+    /// submitted yet, and deletes the others. Returns the error of each mode
+    /// tried before it, or of every mode when none links.
+    unsafe fn finish(
+        self,
+        gl: &glow::Context,
+        vertex_shader_source: &str,
+    ) -> Result<(glow::Program, ShaderMode, Vec<ModeFailure>), Vec<ModeFailure>> {
+        let mut linked = None;
+        let mut failures = Vec::new();
+        for attempt in self.attempts {
+            if linked.is_some() {
+                if let Some(Ok(job)) = attempt.job {
+                    discard_program(gl, job);
+                }
+                continue;
+            }
+            let wrapped = attempt.wrapped;
+            let job = attempt
+                .job
+                .unwrap_or_else(|| submit_program(gl, vertex_shader_source, &wrapped.source));
+            match job.and_then(|job| finish_program(gl, job)) {
+                Ok(program) => linked = Some((program, wrapped.mode)),
+                Err(err) => {
+                    let (log, diagnostics) = remap_log(&err, |line| wrapped.snippet_line(line));
+                    failures.push(ModeFailure {
+                        mode: wrapped.mode,
+                        progress: compile_progress(&err, wrapped.header_lines),
+                        log,
+                        diagnostics,
+                    });
+                }
+            }
+        }
+        match linked {
+            Some((program, mode)) => Ok((program, mode, failures)),
+            None => Err(failures),
+        }
+    }
+
+    unsafe fn discard(self, gl: &glow::Context) {
+        for attempt in self.attempts {
+            if let Some(Ok(job)) = attempt.job {
+                discard_program(gl, job);
+            }
+        }
+    }
+}
+
+struct PendingPass {
+    name: String,
+    channels: [ChannelBinding; 4],
+    settings: [SamplerSettings; 4],
+    build: ProgramBuild,
+}
+
+/// A shader whose programs may still be compiling, from `ShaderState::start`.
+pub struct PendingShader {
+    passes: Vec<PendingPass>,
+    sound: Option<ProgramBuild>,
+    channel_textures: Vec<ChannelTexture>,
+    multipass: bool,
+    vertex_shader_source: String,
+    noise_texture: Option<glow::Texture>,
+    uniforms: Vec<(String, Uniform)>,
+}
+
+impl PendingShader {
+    /// Whether `finish` won't block on the driver.
+    pub fn is_ready(&self, gl: &glow::Context) -> bool {
+        unsafe {
+            self.passes.iter().all(|pass| pass.build.is_ready(gl))
+                && self.sound.as_ref().is_none_or(|sound| sound.is_ready(gl))
+        }
+    }
+
+    /// Drops a shader that was superseded before it finished compiling.
+    pub fn discard(self, gl: &glow::Context) {
+        unsafe {
+            for pass in self.passes {
+                pass.build.discard(gl);
+            }
+            if let Some(sound) = self.sound {
+                sound.discard(gl);
+            }
+            ShaderState::delete_textures(gl, &self.channel_textures);
+        }
+    }
+
+    /// Picks the mode of every pass, blocking until the driver is done.
+    pub fn finish(self, gl: &glow::Context) -> Result<ShaderState, ShaderError> {
+        let Self {
+            passes: pending,
+            sound,
+            channel_textures,
+            multipass,
+            vertex_shader_source,
+            noise_texture,
+            uniforms,
+        } = self;
+
+        let mut passes: Vec<RenderPass> = Vec::with_capacity(pending.len());
+        let mut pending = pending.into_iter();
+        while let Some(pass) = pending.next() {
+            let detection = pass.build.detection;
+            let program = unsafe { pass.build.finish(gl, &vertex_shader_source) }
+                .map_err(|failures| {
+                    let err = mode_error(failures);
+                    if multipass {
+                        err.in_pass(&pass.name)
+                    } else {
+                        err
+                    }
+                })
+                .and_then(|(program, mode, failures)| {
+                    match unsafe { create_samplers(gl, &pass.settings) } {
+                        Ok(samplers) => Ok((program, mode, failures, samplers)),
+                        Err(err) => {
+                            unsafe {
+                                use glow::HasContext as _;
+                                gl.delete_program(program);
+                            }
+                            Err(err.into())
+                        }
+                    }
+                });
+            let (program, mode, failures, samplers) = match program {
+                Ok(program) => program,
+                Err(err) => {
+                    unsafe {
+                        for rest in pending {
+                            rest.build.discard(gl);
+                        }
+                        if let Some(sound) = sound {
+                            sound.discard(gl);
+                        }
+                        ShaderState::delete_passes(gl, &passes);
+                        ShaderState::delete_textures(gl, &channel_textures);
+                    }
+                    return Err(err);
+                }
+            };
+
+            let mipmapped = std::array::from_fn(|i| {
+                matches!(pass.channels[i], ChannelBinding::Buffer(_))
+                    && pass.settings[i].filter == TextureFilter::Mipmap
+            });
+            passes.push(RenderPass {
+                name: pass.name,
+                program,
+                mode,
+                detection,
+                failed_modes: failures
+                    .into_iter()
+                    .map(|failure| (failure.mode, failure.log))
+                    .collect(),
+                channels: pass.channels,
+                samplers,
+                mipmapped,
+                target: None,
+            });
+        }
+
+        let sound_program = match sound {
+            Some(sound) => match unsafe { sound.finish(gl, &vertex_shader_source) } {
+                Ok((program, ..)) => Some(program),
+                Err(mut failures) => {
+                    unsafe {
+                        ShaderState::delete_passes(gl, &passes);
+                        ShaderState::delete_textures(gl, &channel_textures);
+                    }
+                    let failure = failures.remove(0);
+                    let err = ShaderError {
+                        message: failure.log,
+                        pass: None,
+                        mode: Some(failure.mode),
+                        diagnostics: failure.diagnostics,
+                        other_modes: Vec::new(),
+                    };
+                    return Err(err.in_pass(SOUND_PASS));
+                }
+            },
+            None => None,
+        };
+
+        unsafe {
+            use glow::HasContext as _;
+
+            let vertex_array = match gl.create_vertex_array() {
+                Ok(vertex_array) => vertex_array,
+                Err(e) => {
+                    ShaderState::delete_passes(gl, &passes);
+                    ShaderState::delete_textures(gl, &channel_textures);
+                    if let Some(program) = sound_program {
+                        gl.delete_program(program);
+                    }
+                    return Err(format!("Cannot create vertex array: {e}").into());
+                }
+            };
+
+            let (placeholder_texture, owns_placeholder) = match noise_texture {
+                Some(texture) => (texture, false),
+                None => match create_noise_texture(gl) {
+                    Ok(texture) => (texture, true),
+                    Err(err) => {
+                        ShaderState::delete_passes(gl, &passes);
+                        ShaderState::delete_textures(gl, &channel_textures);
+                        if let Some(program) = sound_program {
+                            gl.delete_program(program);
+                        }
+                        gl.delete_vertex_array(vertex_array);
+                        return Err(err.into());
+                    }
+                },
+            };
+
+            Ok(ShaderState {
+                passes,
+                vertex_array,
+                placeholder_texture,
+                owns_placeholder,
+                channel_textures,
+                sound_program,
+                uniforms,
+                target_error: None,
+            })
+        }
+    }
+}
+
+/// A compiled shader: the programs of its passes, their buffers and its
+/// channel textures, drawn with `paint` or read back with `render_frame`.
+///
+/// It owns objects of the GL context it was built on and frees none of them
+/// when dropped. Call `destroy` when done with it, with that context current,
+/// e.g. from `eframe::App::on_exit`; otherwise they leak.
+pub struct ShaderState {
+    passes: Vec<RenderPass>,
+    vertex_array: glow::VertexArray,
+    placeholder_texture: glow::Texture,
+    /// Whether the noise texture is the shader's own, rather than one shared
+    /// through `ShaderOptions::noise_texture`.
+    owns_placeholder: bool,
+    channel_textures: Vec<ChannelTexture>,
+    /// Program of the Sound pass, rendered on demand by `render_sound`.
+    sound_program: Option<glow::Program>,
+    /// Extra uniforms from `ShaderOptions::uniforms`, set on every pass.
+    uniforms: Vec<(String, Uniform)>,
+    /// Why the buffer passes' targets couldn't be allocated at the last
+    /// size, when they couldn't.
+    target_error: Option<String>,
+}
+
+/// Whether the driver can compile shaders in the background
+/// (`GL_KHR_parallel_shader_compile`), for `ShaderState::start`. If so, lets
+/// it pick how many threads to compile on.
+pub fn enable_parallel_compile(gl: &glow::Context) -> bool {
+    use glow::HasContext as _;
+    let extensions = gl.supported_extensions();
+    let supported = extensions.contains("GL_KHR_parallel_shader_compile")
+        || extensions.contains("GL_ARB_parallel_shader_compile");
+    if supported {
+        unsafe { gl.max_shader_compiler_threads(u32::MAX) };
+    }
+    supported
+}
+
+/// Size of the procedural noise texture bound to unassigned channels.
+const NOISE_TEXTURE_SIZE: i32 = 64;
+
+impl ShaderState {
+    pub fn new(
+        gl: &glow::Context,
+        snippet: &str,
+        options: &ShaderOptions,
+    ) -> Result<Self, ShaderError> {
+        Self::start(gl, snippet, options, false)?.finish(gl)
+    }
+
+    /// Resolves the channels of every pass and hands its programs to the
+    /// driver. With `parallel` (`GL_KHR_parallel_shader_compile`) every mode
+    /// of every pass is submitted at once and compiles in the background
+    /// until `PendingShader::is_ready`; otherwise `finish` compiles the modes
+    /// one after another until one links.
+    pub fn start(
+        gl: &glow::Context,
+        snippet: &str,
+        options: &ShaderOptions,
+        parallel: bool,
+    ) -> Result<PendingShader, ShaderError> {
+        let target = GlslTarget::current();
+        let vertex_shader_source = wrapper::vertex_source(target);
+
+        let forced_mode = match options.mode {
+            Some(mode) => Some(mode),
+            None => mode_pragma(snippet)?,
+        };
+        let (common, sources) = split_passes(snippet)?;
+        let multipass = sources.len() > 1;
+        let declarations = options.uniform_declarations();
+
+        // Buffer passes run in the order they appear, the Image pass last.
+        let mut sources = sources;
+        sources.sort_by_key(|p| p.name == IMAGE_PASS);
+
+        // The Sound pass isn't drawn, so it is kept out of the render passes.
+        let sound_source = sources
+            .iter()
+            .position(|p| p.name == SOUND_PASS)
+            .map(|index| sources.remove(index));
+
+        if let Some(image) = sources.last_mut() {
+            for (channel, config) in image.channels.iter_mut().zip(&options.channel_overrides) {
+                if let Some(config) = config {
+                    *channel = config.clone();
+                }
+            }
+        }
+
+        // Resolve buffer names and load channel files before compiling, so a
+        // missing file is reported without waiting for the driver.
+        let mut channel_textures: Vec<ChannelTexture> = Vec::new();
+        let mut bindings: Vec<[ChannelBinding; 4]> = Vec::with_capacity(sources.len());
+        for source in &sources {
+            let mut channels = [ChannelBinding::Noise; 4];
+            for (i, (binding, channel)) in channels.iter_mut().zip(&source.channels).enumerate() {
+                let resolved = match &channel.source {
+                    ChannelSource::Noise => Ok(ChannelBinding::Noise),
+                    ChannelSource::Keyboard => unsafe { create_keyboard_texture(gl) }.map(|texture| {
+                        channel_textures.push(texture);
+                        ChannelBinding::Texture(channel_textures.len() - 1)
+                    }),
+                    ChannelSource::Buffer(name) => {
+                        match sources.iter().position(|p| p.name.eq_ignore_ascii_case(name)) {
+                            Some(index) if sources[index].name != IMAGE_PASS => {
+                                Ok(ChannelBinding::Buffer(index))
+                            }
+                            _ => Err(format!("no buffer pass named `{name}`")),
+                        }
+                    }
+                    ChannelSource::Image(path)
+                    | ChannelSource::Video(path)
+                    | ChannelSource::Audio(path) => {
+                        let path = options.resolve_path(path);
+                        let vflip = channel.sampler().vflip;
+                        let texture = match channel.source {
+                            ChannelSource::Video(_) => unsafe { load_gif_texture(gl, &path, vflip) },
+                            ChannelSource::Audio(_) => unsafe { load_audio_texture(gl, &path) },
+                            _ => unsafe { load_image_texture(gl, &path, vflip) },
+                        };
+                        texture.map(
+                            |texture| {
+                                channel_textures.push(texture);
+                                ChannelBinding::Texture(channel_textures.len() - 1)
+                            },
+                        )
+                    }
+                };
+                match resolved {
+                    Ok(resolved) => *binding = resolved,
+                    Err(err) => {
+                        unsafe { Self::delete_textures(gl, &channel_textures) };
+                        return Err(if multipass {
+                            format!("{} pass: {}: {err}", source.name, CHANNEL_NAMES[i]).into()
+                        } else {
+                            format!("{}: {err}", CHANNEL_NAMES[i]).into()
+                        });
+                    }
+                }
+            }
+            bindings.push(channels);
+        }
+
+        let mut passes = Vec::with_capacity(sources.len());
+        for (source, channels) in sources.iter().zip(bindings) {
+            let mut pass_snippet = common.clone();
+            pass_snippet.append(&source.source);
+            let wrapped = wrapper::wrap_pass(&pass_snippet, forced_mode, target, &declarations);
+            let mut build = ProgramBuild::new(wrapped);
+            if parallel {
+                unsafe { build.submit(gl, &vertex_shader_source) };
+            }
+            passes.push(PendingPass {
+                name: source.name.clone(),
+                channels,
+                settings: source.channels.clone().map(|c| c.sampler()),
+                build,
+            });
+        }
+
+        let sound = sound_source.map(|source| {
+            let mut sound_snippet = common.clone();
+            sound_snippet.append(&source.source);
+            let sound = wrapper::wrap_sound(&sound_snippet, target, SOUND_BLOCK_SIZE[0]);
+            let mut build = ProgramBuild {
+                detection: Detection::Forced(sound.mode),
+                attempts: vec![ModeAttempt {
+                    wrapped: sound,
+                    job: None,
+                }],
+            };
+            if parallel {
+                unsafe { build.submit(gl, &vertex_shader_source) };
+            }
+            build
+        });
+
+        Ok(PendingShader {
+            passes,
+            sound,
+            channel_textures,
+            multipass,
+            vertex_shader_source,
+            noise_texture: options.noise_texture,
+            uniforms: options.uniforms.clone(),
+        })
+    }
+
+    pub fn has_sound(&self) -> bool {
+        self.sound_program.is_some()
+    }
+
+    /// Evaluates the Sound pass for `seconds` of audio and returns the stereo
+    /// samples at `SOUND_SAMPLE_RATE`. The pass is drawn block by block into
+    /// a float target and read back.
+    pub fn render_sound(&self, gl: &glow::Context, seconds: f32) -> Result<Vec<[f32; 2]>, String> {
+        use glow::HasContext as _;
+
+        let Some(program) = self.sound_program else {
+            return Err("Shader has no Sound pass".to_owned());
+        };
+        let total = (seconds.max(0.0) * SOUND_SAMPLE_RATE as f32) as usize;
+        let [width, height] = SOUND_BLOCK_SIZE;
+        let block = (width * height) as usize;
+
+        unsafe {
+            let (framebuffer, texture) =
+                create_render_target(gl, SOUND_BLOCK_SIZE, glow::RGBA32F, glow::RGBA, glow::FLOAT)?;
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(framebuffer));
+            gl.viewport(0, 0, width, height);
+
+            gl.use_program(Some(program));
+            if let Some(loc) = gl.get_uniform_location(program, "iSampleRate") {
+                gl.uniform_1_f32(Some(&loc), SOUND_SAMPLE_RATE as f32);
+            }
+            let offset_location = gl.get_uniform_location(program, "iSampleOffset");
+            gl.bind_vertex_array(Some(self.vertex_array));
+
+            let mut samples = Vec::with_capacity(total);
+            let mut bytes = vec![0u8; block * 4 * 4];
+            while samples.len() < total {
+                gl.uniform_1_i32(offset_location.as_ref(), samples.len() as i32);
+                gl.draw_arrays(glow::TRIANGLES, 0, 3);
+                gl.read_pixels(
+                    0,
+                    0,
+                    width,
+                    height,
+                    glow::RGBA,
+                    glow::FLOAT,
+                    glow::PixelPackData::Slice(Some(bytes.as_mut_slice())),
+                );
+
+                let count = (total - samples.len()).min(block);
+                samples.extend(bytes.chunks_exact(16).take(count).map(|pixel| {
+                    let left = f32::from_ne_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]);
+                    let right = f32::from_ne_bytes([pixel[4], pixel[5], pixel[6], pixel[7]]);
+                    [left, right]
+                }));
+            }
+
+            gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+            gl.delete_texture(texture);
+            gl.delete_framebuffer(framebuffer);
+
+            Ok(samples)
+        }
+    }
+
+    /// Deletes the programs and targets of passes built so far, when a later
+    /// pass fails to compile.
+    unsafe fn delete_passes(gl: &glow::Context, passes: &[RenderPass]) {
+        use glow::HasContext as _;
+        for pass in passes {
+            gl.delete_program(pass.program);
+            for sampler in pass.samplers {
+                gl.delete_sampler(sampler);
+            }
+            if let Some(target) = &pass.target {
+                Self::delete_target(gl, target);
+            }
+        }
+    }
+
+    unsafe fn delete_textures(gl: &glow::Context, textures: &[ChannelTexture]) {
+        use glow::HasContext as _;
+        for texture in textures {
+            gl.delete_texture(texture.texture);
+        }
+    }
+
+    unsafe fn delete_target(gl: &glow::Context, target: &PassTarget) {
+        use glow::HasContext as _;
+        for (framebuffer, texture) in target.framebuffers.iter().zip(&target.textures) {
+            gl.delete_framebuffer(*framebuffer);
+            gl.delete_texture(*texture);
+        }
+    }
+
+    /// Deletes every GL object the shader owns. It must not be drawn after
+    /// this.
+    pub fn destroy(&self, gl: &glow::Context) {
+        unsafe {
+            use glow::HasContext as _;
+            Self::delete_passes(gl, &self.passes);
+            Self::delete_textures(gl, &self.channel_textures);
+            if let Some(program) = self.sound_program {
+                gl.delete_program(program);
+            }
+            gl.delete_vertex_array(self.vertex_array);
+            if self.owns_placeholder {
+                gl.delete_texture(self.placeholder_texture);
+            }
+        }
+    }
+
+    /// Mode the Image pass compiled in.
+    pub fn mode(&self) -> ShaderMode {
+        self.passes[self.passes.len() - 1].mode
+    }
+
+    /// Why the Image pass's modes were tried in the order they were.
+    pub fn detection(&self) -> Detection {
+        self.passes[self.passes.len() - 1].detection
+    }
+
+    /// Logs of the modes the Image pass was tried in before `mode`, with the
+    /// snippet's line numbers.
+    pub fn failed_modes(&self) -> &[(ShaderMode, String)] {
+        &self.passes[self.passes.len() - 1].failed_modes
+    }
+
+    /// Changes the value of an extra uniform for the next frames. Tweet and
+    /// Shadertoy code only have the uniforms declared when the shader was
+    /// built; the value's type has to match the declaration.
+    pub fn set_uniform(&mut self, name: &str, value: Uniform) {
+        match self.uniforms.iter_mut().find(|(n, _)| n == name) {
+            Some((_, current)) => *current = value,
+            None => self.uniforms.push((name.to_owned(), value)),
+        }
+    }
+
+    /// Why the last frame drew without its buffer passes: their render
+    /// targets couldn't be allocated at that size.
+    pub fn target_error(&self) -> Option<&str> {
+        self.target_error.as_deref()
+    }
+
+    /// Names of the passes in the order they are rendered.
+    pub fn pass_names(&self) -> Vec<&str> {
+        self.passes.iter().map(|p| p.name.as_str()).collect()
+    }
+
+    /// (Re)allocates the ping-pong targets of all buffer passes when the
+    /// output size changes. Like on Shadertoy, resizing clears the buffers.
+    unsafe fn ensure_targets(&mut self, gl: &glow::Context, size: [i32; 2]) -> Result<(), String> {
+        let (internal_format, ty) = if cfg!(target_arch = "wasm32") {
+            (glow::RGBA16F, glow::HALF_FLOAT)
+        } else {
+            (glow::RGBA32F, glow::FLOAT)
+        };
+
+        for pass in self.passes.iter_mut().filter(|p| p.name != IMAGE_PASS) {
+            if pass.target.as_ref().is_some_and(|t| t.size == size) {
+                continue;
+            }
+            if let Some(target) = pass.target.take() {
+                Self::delete_target(gl, &target);
+            }
+
+            let (fb0, tex0) =
+                create_render_target(gl, size, internal_format, glow::RGBA, ty)?;
+            let (fb1, tex1) = create_render_target(gl, size, internal_format, glow::RGBA, ty)
+                .inspect_err(|_| {
+                    use glow::HasContext as _;
+                    gl.delete_framebuffer(fb0);
+                    gl.delete_texture(tex0);
+                })?;
+            pass.target = Some(PassTarget {
+                framebuffers: [fb0, fb1],
+                textures: [tex0, tex1],
+                size,
+                front: 0,
+            });
+        }
+        Ok(())
+    }
+
+    /// Clears both targets of every buffer pass, so the next frame starts
+    /// from empty buffers like a new shader's.
+    unsafe fn clear_targets(&mut self, gl: &glow::Context) {
+        use glow::HasContext as _;
+        let previous_framebuffer = gl.get_parameter_framebuffer(glow::FRAMEBUFFER_BINDING);
+        gl.clear_color(0.0, 0.0, 0.0, 0.0);
+        for target in self.passes.iter_mut().filter_map(|pass| pass.target.as_mut()) {
+            for framebuffer in target.framebuffers {
+                gl.bind_framebuffer(glow::FRAMEBUFFER, Some(framebuffer));
+                gl.clear(glow::COLOR_BUFFER_BIT);
+            }
+            target.front = 0;
+        }
+        gl.bind_framebuffer(glow::FRAMEBUFFER, previous_framebuffer);
+    }
+
+    /// Draws one frame into the current framebuffer, in the region whose
+    /// bottom-left corner is `rect_min` and whose size is `resolution`, both
+    /// in framebuffer pixels like `gl_FragCoord`.
+    pub fn paint(
+        &mut self,
+        gl: &glow::Context,
+        clock: &FrameClock,
+        rect_min: egui::Pos2,
+        resolution: egui::Vec2,
+        mouse: &MouseInput,
+        keyboard: &KeyboardInput,
+    ) {
+        use glow::HasContext as _;
+        unsafe {
+            self.update_streams(gl, clock.time, keyboard);
+
+            let image_index = self.passes.len() - 1;
+
+            if image_index > 0 {
+                let size = [
+                    (resolution.x.round() as i32).max(1),
+                    (resolution.y.round() as i32).max(1),
+                ];
+
+                // Buffer passes render into their own framebuffers, so save
+                // the caller's framebuffer, viewport and scissor.
+                let previous_framebuffer = gl.get_parameter_framebuffer(glow::FRAMEBUFFER_BINDING);
+                let mut viewport = [0i32; 4];
+                gl.get_parameter_i32_slice(glow::VIEWPORT, &mut viewport);
+                let scissor = gl.is_enabled(glow::SCISSOR_TEST);
+                gl.disable(glow::SCISSOR_TEST);
+
+                // Without targets the buffer passes aren't drawn this frame.
+                self.target_error = self.ensure_targets(gl, size).err();
+                if self.target_error.is_none() {
+                    gl.viewport(0, 0, size[0], size[1]);
+                    for index in 0..image_index {
+                        let Some(target) = &self.passes[index].target else {
+                            continue;
+                        };
+                        let back = 1 - target.front;
+                        gl.bind_framebuffer(glow::FRAMEBUFFER, Some(target.framebuffers[back]));
+                        self.draw_pass(gl, index, clock, egui::Pos2::ZERO, resolution, mouse);
+                        if let Some(target) = self.passes[index].target.as_mut() {
+                            target.front = back;
+                        }
+                    }
+                }
+
+                gl.bind_framebuffer(glow::FRAMEBUFFER, previous_framebuffer);
+                gl.viewport(viewport[0], viewport[1], viewport[2], viewport[3]);
+                if scissor {
+                    gl.enable(glow::SCISSOR_TEST);
+                }
+            }
+
+            gl.clear_color(0.0, 0.0, 0.0, 1.0);
+            gl.clear(glow::COLOR_BUFFER_BIT);
+
+            self.draw_pass(gl, image_index, clock, rect_min, resolution, mouse);
+        }
+    }
+
+    /// Uploads the current frame of every video channel, the spectrum and
+    /// waveform of every audio channel and the keyboard state.
+    unsafe fn update_streams(&mut self, gl: &glow::Context, time: f32, keyboard: &KeyboardInput) {
+        use glow::HasContext as _;
+        for channel in &mut self.channel_textures {
+            channel.time = time;
+            let (format, data) = match channel.stream.as_mut() {
+                Some(ChannelStream::Video(video)) => {
+                    let (frame, position) = video.frame_at(time);
+                    channel.time = position;
+                    if frame == video.current {
+                        continue;
+                    }
+                    video.current = frame;
+                    (glow::RGBA, std::borrow::Cow::Borrowed(&video.frames[frame][..]))
+                }
+                Some(ChannelStream::Audio(audio)) => {
+                    let (index, position) = audio.sample_at(time);
+                    channel.time = position;
+                    if audio.current == Some(index) {
+                        continue;
+                    }
+                    audio.current = Some(index);
+                    (glow::RED, std::borrow::Cow::Owned(audio.texture_data(index)))
+                }
+                Some(ChannelStream::Keyboard) => {
+                    (glow::RED, std::borrow::Cow::Borrowed(&keyboard.texels[..]))
+                }
+                None => continue,
+            };
+
+            gl.bind_texture(glow::TEXTURE_2D, Some(channel.texture));
+            gl.tex_sub_image_2d(
+                glow::TEXTURE_2D,
+                0,
+                0,
+                0,
+                channel.size[0] as i32,
+                channel.size[1] as i32,
+                format,
+                glow::UNSIGNED_BYTE,
+                glow::PixelUnpackData::Slice(Some(&data)),
+            );
+            gl.generate_mipmap(glow::TEXTURE_2D);
+            gl.bind_texture(glow::TEXTURE_2D, None);
+        }
+    }
+
+    /// Sets the uniforms and channels of one pass and draws it into the
+    /// currently bound framebuffer.
+    unsafe fn draw_pass(
+        &self,
+        gl: &glow::Context,
+        index: usize,
+        clock: &FrameClock,
+        rect_min: egui::Pos2,
+        resolution: egui::Vec2,
+        mouse: &MouseInput,
+    ) {
+        use glow::HasContext as _;
+        let pass = &self.passes[index];
+        let program = pass.program;
+        let time = clock.time;
+
+        gl.use_program(Some(program));
+
+        if let Some(loc) = gl.get_uniform_location(program, "t") {
+            gl.uniform_1_f32(Some(&loc), time);
+        }
+        if let Some(loc) = gl.get_uniform_location(program, "r") {
+            gl.uniform_2_f32(Some(&loc), resolution.x, resolution.y);
+        }
+        if let Some(loc) = gl.get_uniform_location(program, "m") {
+            let [x, y] = mouse.hover;
+            gl.uniform_2_f32(Some(&loc), x / resolution.x, y / resolution.y);
+        }
+        if let Some(loc) = gl.get_uniform_location(program, "rect_min") {
+            gl.uniform_2_f32(Some(&loc), rect_min.x, rect_min.y);
+        }
+
+        // Shadertoy uniforms
+        if let Some(loc) = gl.get_uniform_location(program, "iTime") {
+            gl.uniform_1_f32(Some(&loc), time);
+        }
+        if let Some(loc) = gl.get_uniform_location(program, "iTimeDelta") {
+            gl.uniform_1_f32(Some(&loc), clock.time_delta);
+        }
+        if let Some(loc) = gl.get_uniform_location(program, "iFrameRate") {
+            gl.uniform_1_f32(Some(&loc), clock.frame_rate);
+        }
+        if let Some(loc) = gl.get_uniform_location(program, "iDate") {
+            let [year, month, day, seconds] = clock.date;
+            gl.uniform_4_f32(Some(&loc), year, month, day, seconds);
+        }
+        if let Some(loc) = gl.get_uniform_location(program, "iSampleRate") {
+            gl.uniform_1_f32(Some(&loc), SOUND_SAMPLE_RATE as f32);
+        }
+        if let Some(loc) = gl.get_uniform_location(program, "iResolution") {
+            gl.uniform_3_f32(Some(&loc), resolution.x, resolution.y, 1.0);
+        }
+        if let Some(loc) = gl.get_uniform_location(program, "iMouse") {
+            let [x, y, z, w] = mouse.shadertoy();
+            gl.uniform_4_f32(Some(&loc), x, y, z, w);
+        }
+        if let Some(loc) = gl.get_uniform_location(program, "iFrame") {
+            gl.uniform_1_i32(Some(&loc), clock.frame);
+        }
+        // Bind textures for iChannel0-3
+        let mut channel_time = [time; 4];
+        let mut channel_resolution = [0.0f32; 12];
+        for (i, (name, channel)) in CHANNEL_NAMES.iter().zip(&pass.channels).enumerate() {
+            let noise = (
+                self.placeholder_texture,
+                [NOISE_TEXTURE_SIZE as f32, NOISE_TEXTURE_SIZE as f32],
+            );
+            let (texture, size) = match *channel {
+                ChannelBinding::Buffer(source) => match &self.passes[source].target {
+                    Some(target) => (
+                        target.textures[target.front],
+                        [target.size[0] as f32, target.size[1] as f32],
+                    ),
+                    None => noise,
+                },
+                ChannelBinding::Texture(index) => {
+                    let channel_texture = &self.channel_textures[index];
+                    channel_time[i] = channel_texture.time;
+                    (
+                        channel_texture.texture,
+                        [channel_texture.size[0] as f32, channel_texture.size[1] as f32],
+                    )
+                }
+                ChannelBinding::Noise => noise,
+            };
+            channel_resolution[i * 3] = size[0];
+            channel_resolution[i * 3 + 1] = size[1];
+            channel_resolution[i * 3 + 2] = 1.0;
+
+            gl.active_texture(glow::TEXTURE0 + i as u32);
+            gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+            if pass.mipmapped[i] {
+                gl.generate_mipmap(glow::TEXTURE_2D);
+            }
+            gl.bind_sampler(i as u32, Some(pass.samplers[i]));
+            if let Some(loc) = gl.get_uniform_location(program, name) {
+                gl.uniform_1_i32(Some(&loc), i as i32);
+            }
+        }
+        if let Some(loc) = gl.get_uniform_location(program, "iChannelTime") {
+            gl.uniform_1_f32_slice(Some(&loc), &channel_time);
+        }
+        if let Some(loc) = gl.get_uniform_location(program, "iChannelResolution") {
+            gl.uniform_3_f32_slice(Some(&loc), &channel_resolution);
+        }
+        for (name, value) in &self.uniforms {
+            if let Some(loc) = gl.get_uniform_location(program, name) {
+                value.apply(gl, &loc);
+            }
+        }
+
+        gl.bind_vertex_array(Some(self.vertex_array));
+        gl.draw_arrays(glow::TRIANGLES, 0, 3);
+
+        // Samplers override texture parameters, so don't leave them bound
+        // for egui's own textures.
+        for i in 0..CHANNEL_NAMES.len() {
+            gl.bind_sampler(i as u32, None);
+        }
+        gl.active_texture(glow::TEXTURE0);
+    }
+
+    /// Draws one frame offscreen and reads it back as RGBA rows, top row
+    /// first.
+    pub fn render_to_image(
+        &mut self,
+        gl: &glow::Context,
+        clock: &FrameClock,
+        size: [u32; 2],
+    ) -> Result<Vec<u8>, String> {
+        use glow::HasContext as _;
+
+        let width = size[0] as i32;
+        let height = size[1] as i32;
+
+        unsafe {
+            let (framebuffer, texture) = create_render_target(
+                gl,
+                [width, height],
+                glow::RGBA8,
+                glow::RGBA,
+                glow::UNSIGNED_BYTE,
+            )?;
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(framebuffer));
+            gl.viewport(0, 0, width, height);
+
+            self.paint(
+                gl,
+                clock,
+                egui::Pos2::new(0.0, 0.0),
+                egui::vec2(width as f32, height as f32),
+                &MouseInput::default(),
+                &KeyboardInput::default(),
+            );
+
+            let mut pixels = vec![0u8; (width * height * 4) as usize];
+            gl.read_pixels(
+                0,
+                0,
+                width,
+                height,
+                glow::RGBA,
+                glow::UNSIGNED_BYTE,
+                glow::PixelPackData::Slice(Some(pixels.as_mut_slice())),
+            );
+
+            gl.bind_texture(glow::TEXTURE_2D, None);
+            gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+            gl.delete_texture(texture);
+            gl.delete_framebuffer(framebuffer);
+
+            if let Some(err) = &self.target_error {
+                return Err(format!("Cannot allocate buffer targets: {err}"));
+            }
+
+            // GL reads rows bottom-up.
+            let row = width as usize * 4;
+            Ok(pixels.chunks_exact(row).rev().flatten().copied().collect())
+        }
+    }
+
+    /// Renders the frame at `time` seconds of an animation at `fps` offscreen,
+    /// as RGBA rows, top row first. Shaders with buffer passes run every
+    /// frame from the start on cleared buffers, since their buffers build up
+    /// over frames, so the result doesn't depend on earlier renders.
+    pub fn render_frame(
+        &mut self,
+        gl: &glow::Context,
+        time: f32,
+        fps: u32,
+        size: [u32; 2],
+    ) -> Result<Vec<u8>, String> {
+        let last = (time * fps as f32).round() as u32;
+        let first = if self.passes.len() > 1 { 0 } else { last };
+        if first == 0 {
+            unsafe { self.clear_targets(gl) };
+        }
+        let mut pixels = Vec::new();
+        for frame in first..=last {
+            let mut clock = FrameClock::fixed(frame, fps);
+            if frame == last {
+                clock.time = time;
+            }
+            pixels = self.render_to_image(gl, &clock, size)?;
+        }
+        Ok(pixels)
+    }
+}
+
+/// Decodes a PNG or JPEG file into an RGBA texture with mipmaps. With `vflip`
+/// rows are flipped so that `uv.y = 0` is the bottom of the image, as on
+/// Shadertoy. Filtering and wrapping come from the channel's sampler.
+unsafe fn load_image_texture(
+    gl: &glow::Context,
+    path: &Path,
+    vflip: bool,
+) -> Result<ChannelTexture, String> {
+    use glow::HasContext as _;
+
+    let image = image::open(path)
+        .map_err(|e| format!("Failed to load image {}: {e}", path.display()))?;
+    let image = if vflip { image.flipv() } else { image }.to_rgba8();
+    let (width, height) = image.dimensions();
+
+    let texture = gl
+        .create_texture()
+        .map_err(|e| format!("Cannot create texture: {e}"))?;
+    gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+    gl.tex_image_2d(
+        glow::TEXTURE_2D,
+        0,
+        glow::RGBA8 as i32,
+        width as i32,
+        height as i32,
+        0,
+        glow::RGBA,
+        glow::UNSIGNED_BYTE,
+        glow::PixelUnpackData::Slice(Some(image.as_raw())),
+    );
+    gl.generate_mipmap(glow::TEXTURE_2D);
+    gl.bind_texture(glow::TEXTURE_2D, None);
+
+    Ok(ChannelTexture {
+        texture,
+        size: [width, height],
+        stream: None,
+        time: 0.0,
+    })
+}
+
+/// Decodes every frame of an animated GIF into full RGBA canvases and
+/// uploads the first one. Later frames are uploaded by
+/// [`ShaderState::update_streams`].
+unsafe fn load_gif_texture(
+    gl: &glow::Context,
+    path: &Path,
+    vflip: bool,
+) -> Result<ChannelTexture, String> {
+    use glow::HasContext as _;
+
+    let gif_error = |e: gif::DecodingError| format!("Failed to decode GIF {}: {e}", path.display());
+    let file = File::open(path).map_err(|e| format!("Failed to open GIF {}: {e}", path.display()))?;
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::RGBA);
+    let mut decoder = options.read_info(BufReader::new(file)).map_err(gif_error)?;
+
+    let width = decoder.width() as usize;
+    let height = decoder.height() as usize;
+    let mut canvas = vec![0u8; width * height * 4];
+    let mut frames = Vec::new();
+    let mut frame_ends = Vec::new();
+    let mut elapsed = 0.0f32;
+
+    while let Some(frame) = decoder.read_next_frame().map_err(gif_error)? {
+        let previous = (frame.dispose == gif::DisposalMethod::Previous).then(|| canvas.clone());
+
+        // Blit the frame's rectangle, skipping transparent pixels.
+        let (left, top) = (frame.left as usize, frame.top as usize);
+        for y in 0..frame.height as usize {
+            for x in 0..frame.width as usize {
+                let (cx, cy) = (left + x, top + y);
+                if cx >= width || cy >= height {
+                    continue;
+                }
+                let src = (y * frame.width as usize + x) * 4;
+                if frame.buffer[src + 3] == 0 {
+                    continue;
+                }
+                let dst = (cy * width + cx) * 4;
+                canvas[dst..dst + 4].copy_from_slice(&frame.buffer[src..src + 4]);
+            }
+        }
+
+        if vflip {
+            frames.push(canvas.chunks_exact(width * 4).rev().flatten().copied().collect());
+        } else {
+            frames.push(canvas.clone());
+        }
+
+        // Delays are in hundredths of a second; like browsers, treat tiny
+        // delays as 100 ms.
+        let delay = if frame.delay <= 1 { 10 } else { frame.delay };
+        elapsed += delay as f32 / 100.0;
+        frame_ends.push(elapsed);
+
+        match frame.dispose {
+            gif::DisposalMethod::Background => {
+                for y in top..(top + frame.height as usize).min(height) {
+                    for x in left..(left + frame.width as usize).min(width) {
+                        let dst = (y * width + x) * 4;
+                        canvas[dst..dst + 4].fill(0);
+                    }
+                }
+            }
+            gif::DisposalMethod::Previous => {
+                if let Some(previous) = previous {
+                    canvas = previous;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if frames.is_empty() {
+        return Err(format!("GIF {} has no frames", path.display()));
+    }
+
+    let texture = gl
+        .create_texture()
+        .map_err(|e| format!("Cannot create texture: {e}"))?;
+    gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+    gl.tex_image_2d(
+        glow::TEXTURE_2D,
+        0,
+        glow::RGBA8 as i32,
+        width as i32,
+        height as i32,
+        0,
+        glow::RGBA,
+        glow::UNSIGNED_BYTE,
+        glow::PixelUnpackData::Slice(Some(&frames[0])),
+    );
+    gl.generate_mipmap(glow::TEXTURE_2D);
+    gl.bind_texture(glow::TEXTURE_2D, None);
+
+    Ok(ChannelTexture {
+        texture,
+        size: [width as u32, height as u32],
+        stream: Some(ChannelStream::Video(VideoFrames {
+            frames,
+            frame_ends,
+            current: 0,
+        })),
+        time: 0.0,
+    })
+}
+
+/// Decodes a WAV file into mono samples and creates the 512×2 texture they
+/// are analysed into. The texture is filled by [`ShaderState::update_streams`].
+unsafe fn load_audio_texture(gl: &glow::Context, path: &Path) -> Result<ChannelTexture, String> {
+    use glow::HasContext as _;
+
+    let wav_error = |e: hound::Error| format!("Failed to decode WAV {}: {e}", path.display());
+    let mut reader = hound::WavReader::open(path).map_err(wav_error)?;
+    let spec = reader.spec();
+    let interleaved: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader
+            .samples::<f32>()
+            .collect::<Result<_, _>>()
+            .map_err(wav_error)?,
+        hound::SampleFormat::Int => {
+            let scale = 1.0 / (1u64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map(|s| s.map(|s| s as f32 * scale))
+                .collect::<Result<_, _>>()
+                .map_err(wav_error)?
+        }
+    };
+    let channels = spec.channels.max(1) as usize;
+    let samples: Vec<f32> = interleaved
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+        .collect();
+    if samples.is_empty() {
+        return Err(format!("WAV {} has no samples", path.display()));
+    }
+
+    let texture = gl
+        .create_texture()
+        .map_err(|e| format!("Cannot create texture: {e}"))?;
+    gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+    gl.tex_image_2d(
+        glow::TEXTURE_2D,
+        0,
+        glow::R8 as i32,
+        AUDIO_TEXTURE_WIDTH as i32,
+        2,
+        0,
+        glow::RED,
+        glow::UNSIGNED_BYTE,
+        glow::PixelUnpackData::Slice(None),
+    );
+    gl.bind_texture(glow::TEXTURE_2D, None);
+
+    Ok(ChannelTexture {
+        texture,
+        size: [AUDIO_TEXTURE_WIDTH as u32, 2],
+        stream: Some(ChannelStream::Audio(AudioTrack {
+            samples,
+            sample_rate: spec.sample_rate,
+            current: None,
+        })),
+        time: 0.0,
+    })
+}
+
+/// Creates the small procedural noise texture bound to unassigned
+/// `iChannel*` uniforms, to share through `ShaderOptions::noise_texture`.
+///
+/// # Safety
+///
+/// `gl` has to be current on this thread.
+pub unsafe fn create_noise_texture(gl: &glow::Context) -> Result<glow::Texture, String> {
+    use glow::HasContext as _;
+
+    let tex = gl
+        .create_texture()
+        .map_err(|e| format!("Cannot create texture: {e}"))?;
+    gl.bind_texture(glow::TEXTURE_2D, Some(tex));
+
+    let size = NOISE_TEXTURE_SIZE;
+    let mut data = vec![0u8; (size * size * 4) as usize];
+    for y in 0..size {
+        for x in 0..size {
+            let idx = ((y * size + x) * 4) as usize;
+            let mut seed =
+                (x as u32).wrapping_mul(1973) ^ (y as u32).wrapping_mul(9277) ^ 0x7feb_352d;
+            seed = seed.wrapping_mul(0x27d4_eb2d);
+            let v = (seed >> 24) as u8;
+            data[idx] = v;
+            data[idx + 1] = v;
+            data[idx + 2] = v;
+            data[idx + 3] = 255;
+        }
+    }
+
+    gl.tex_image_2d(
+        glow::TEXTURE_2D,
+        0,
+        glow::RGBA8 as i32,
+        size,
+        size,
+        0,
+        glow::RGBA,
+        glow::UNSIGNED_BYTE,
+        glow::PixelUnpackData::Slice(Some(&data)),
+    );
+    gl.generate_mipmap(glow::TEXTURE_2D);
+    gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MIN_FILTER, glow::NEAREST as i32);
+    gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MAG_FILTER, glow::NEAREST as i32);
+    gl.bind_texture(glow::TEXTURE_2D, None);
+    Ok(tex)
+}
+
+/// Creates the R8 texture of a keyboard channel, filled each frame by
+/// `update_streams`.
+unsafe fn create_keyboard_texture(gl: &glow::Context) -> Result<ChannelTexture, String> {
+    use glow::HasContext as _;
+    let texture = gl
+        .create_texture()
+        .map_err(|e| format!("Cannot create texture: {e}"))?;
+    gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+    gl.tex_image_2d(
+        glow::TEXTURE_2D,
+        0,
+        glow::R8 as i32,
+        KEYBOARD_KEYS as i32,
+        3,
+        0,
+        glow::RED,
+        glow::UNSIGNED_BYTE,
+        glow::PixelUnpackData::Slice(None),
+    );
+    gl.bind_texture(glow::TEXTURE_2D, None);
+
+    Ok(ChannelTexture {
+        texture,
+        size: [KEYBOARD_KEYS as u32, 3],
+        stream: Some(ChannelStream::Keyboard),
+        time: 0.0,
+    })
+}
+
+/// Writes RGBA rows, as from `ShaderState::render_frame`, as a PNG file.
+pub fn write_png(path: &Path, rgba: &[u8], size: [u32; 2]) -> Result<(), String> {
+    image::save_buffer_with_format(
+        path,
+        rgba,
+        size[0],
+        size[1],
+        image::ExtendedColorType::Rgba8,
+        image::ImageFormat::Png,
+    )
+    .map_err(|e| format!("Failed to write {}: {e}", path.display()))
+}
+
+/// Writes stereo samples as a 16-bit WAV file at `SOUND_SAMPLE_RATE`.
+pub fn write_sound_wav(path: &Path, samples: &[[f32; 2]]) -> Result<(), String> {
+    let spec = hound::WavSpec {
+        channels: 2,
+        sample_rate: SOUND_SAMPLE_RATE,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let wav_error = |e: hound::Error| format!("Failed to write WAV {}: {e}", path.display());
+    let mut writer = hound::WavWriter::create(path, spec).map_err(wav_error)?;
+    for sample in samples.iter().flatten() {
+        let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        writer.write_sample(value).map_err(wav_error)?;
+    }
+    writer.finalize().map_err(wav_error)
+}
+
+/// Creates one sampler object per channel with the given filtering and wrap.
+unsafe fn create_samplers(
+    gl: &glow::Context,
+    settings: &[SamplerSettings; 4],
+) -> Result<[glow::Sampler; 4], String> {
+    use glow::HasContext as _;
+
+    let mut samplers = Vec::with_capacity(settings.len());
+    for settings in settings {
+        let sampler = match gl.create_sampler() {
+            Ok(sampler) => sampler,
+            Err(e) => {
+                for sampler in samplers {
+                    gl.delete_sampler(sampler);
+                }
+                return Err(format!("Cannot create sampler: {e}"));
+            }
+        };
+        let (min_filter, mag_filter) = match settings.filter {
+            TextureFilter::Nearest => (glow::NEAREST, glow::NEAREST),
+            TextureFilter::Linear => (glow::LINEAR, glow::LINEAR),
+            TextureFilter::Mipmap => (glow::LINEAR_MIPMAP_LINEAR, glow::LINEAR),
+        };
+        let wrap = match settings.wrap {
+            TextureWrap::Clamp => glow::CLAMP_TO_EDGE,
+            TextureWrap::Repeat => glow::REPEAT,
+        };
+        gl.sampler_parameter_i32(sampler, glow::TEXTURE_MIN_FILTER, min_filter as i32);
+        gl.sampler_parameter_i32(sampler, glow::TEXTURE_MAG_FILTER, mag_filter as i32);
+        gl.sampler_parameter_i32(sampler, glow::TEXTURE_WRAP_S, wrap as i32);
+        gl.sampler_parameter_i32(sampler, glow::TEXTURE_WRAP_T, wrap as i32);
+        samplers.push(sampler);
+    }
+
+    Ok([samplers[0], samplers[1], samplers[2], samplers[3]])
+}
+
+/// Creates a framebuffer with a single color texture attachment of the given
+/// format. Leaves no framebuffer or texture bound.
+unsafe fn create_render_target(
+    gl: &glow::Context,
+    size: [i32; 2],
+    internal_format: u32,
+    format: u32,
+    ty: u32,
+) -> Result<(glow::Framebuffer, glow::Texture), String> {
+    use glow::HasContext as _;
+
+    let [width, height] = size;
+
+    let previous_framebuffer = gl.get_parameter_framebuffer(glow::FRAMEBUFFER_BINDING);
+    let framebuffer = gl
+        .create_framebuffer()
+        .map_err(|e| format!("Failed to create framebuffer: {e}"))?;
+    gl.bind_framebuffer(glow::FRAMEBUFFER, Some(framebuffer));
+
+    let texture = match gl.create_texture() {
+        Ok(texture) => texture,
+        Err(e) => {
+            gl.bind_framebuffer(glow::FRAMEBUFFER, previous_framebuffer);
+            gl.delete_framebuffer(framebuffer);
+            return Err(format!("Failed to create texture: {e}"));
+        }
+    };
+    gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+    gl.tex_image_2d(
+        glow::TEXTURE_2D,
+        0,
+        internal_format as i32,
+        width,
+        height,
+        0,
+        format,
+        ty,
+        glow::PixelUnpackData::Slice(None),
+    );
+    gl.tex_parameter_i32(
+        glow::TEXTURE_2D,
+        glow::TEXTURE_MIN_FILTER,
+        glow::LINEAR as i32,
+    );
+    gl.tex_parameter_i32(
+        glow::TEXTURE_2D,
+        glow::TEXTURE_MAG_FILTER,
+        glow::LINEAR as i32,
+    );
+    gl.tex_parameter_i32(
+        glow::TEXTURE_2D,
+        glow::TEXTURE_WRAP_S,
+        glow::CLAMP_TO_EDGE as i32,
+    );
+    gl.tex_parameter_i32(
+        glow::TEXTURE_2D,
+        glow::TEXTURE_WRAP_T,
+        glow::CLAMP_TO_EDGE as i32,
+    );
+    gl.framebuffer_texture_2d(
+        glow::FRAMEBUFFER,
+        glow::COLOR_ATTACHMENT0,
+        glow::TEXTURE_2D,
+        Some(texture),
+        0,
+    );
+
+    let complete = gl.check_framebuffer_status(glow::FRAMEBUFFER) == glow::FRAMEBUFFER_COMPLETE;
+    if complete {
+        // Start from a cleared target so feedback passes begin at zero.
+        gl.clear_color(0.0, 0.0, 0.0, 0.0);
+        gl.clear(glow::COLOR_BUFFER_BIT);
+    }
+
+    gl.bind_texture(glow::TEXTURE_2D, None);
+    gl.bind_framebuffer(glow::FRAMEBUFFER, previous_framebuffer);
+
+    if !complete {
+        gl.delete_texture(texture);
+        gl.delete_framebuffer(framebuffer);
+        return Err("Framebuffer is not complete".to_owned());
+    }
+
+    Ok((framebuffer, texture))
+}
+
+/// An OpenGL 3.3 context with no window, for the CLI and offscreen renders.
+/// It is made current without a surface when the driver allows it (Mesa
+/// does, llvmpipe too), and on a 1×1 pbuffer otherwise.
+#[cfg(target_os = "linux")]
+pub struct HeadlessGl {
+    pub gl: glow::Context,
+    // Dropped after `gl`, which needs them current.
+    _surface: Option<glutin::api::egl::surface::Surface<glutin::surface::PbufferSurface>>,
+    _context: glutin::api::egl::context::PossiblyCurrentContext,
+}
+
+#[cfg(target_os = "linux")]
+impl HeadlessGl {
+    /// Opens the context on the first EGL device that has one.
+    pub fn new() -> Result<Self, String> {
+        Self::open(|_| true)
+    }
+
+    /// Opens the context on Mesa's software renderer, llvmpipe, whose
+    /// output doesn't depend on the machine's GPU.
+    pub fn software() -> Result<Self, String> {
+        Self::open(|device| device.extensions().contains("EGL_MESA_device_software"))
+    }
+
+    fn open(accept: impl Fn(&glutin::api::egl::device::Device) -> bool) -> Result<Self, String> {
+        let devices = glutin::api::egl::device::Device::query_devices()
+            .map_err(|e| format!("Cannot list EGL devices: {e}"))?;
+        let mut error = "No EGL device".to_owned();
+        for device in devices.filter(|device| accept(device)) {
+            match Self::with_device(&device) {
+                Ok(headless) => return Ok(headless),
+                Err(err) => error = err,
+            }
+        }
+        Err(error)
+    }
+
+    fn with_device(device: &glutin::api::egl::device::Device) -> Result<Self, String> {
+        use glutin::api::egl::display::Display;
+        use glutin::config::{ConfigSurfaceTypes, ConfigTemplateBuilder};
+        use glutin::context::{ContextApi, ContextAttributesBuilder, GlProfile, Version};
+        use glutin::prelude::*;
+        use glutin::surface::{PbufferSurface, SurfaceAttributesBuilder};
+
+        let display = unsafe { Display::with_device(device, None) }
+            .map_err(|e| format!("Cannot open EGL display: {e}"))?;
+        let attributes = ContextAttributesBuilder::new()
+            .with_profile(GlProfile::Core)
+            .with_context_api(ContextApi::OpenGl(Some(Version::new(3, 3))))
+            .build(None);
+
+        let surfaceless = ConfigTemplateBuilder::new()
+            .with_surface_type(ConfigSurfaceTypes::empty())
+            .build();
+        let context = unsafe { display.find_configs(surfaceless) }
+            .ok()
+            .and_then(|mut configs| configs.next())
+            .and_then(|config| unsafe { display.create_context(&config, &attributes) }.ok())
+            .and_then(|context| context.make_current_surfaceless().ok());
+
+        let (context, surface) = match context {
+            Some(context) => (context, None),
+            None => {
+                let template = ConfigTemplateBuilder::new()
+                    .with_surface_type(ConfigSurfaceTypes::PBUFFER)
+                    .build();
+                let config = unsafe { display.find_configs(template) }
+                    .map_err(|e| format!("Cannot find an EGL config: {e}"))?
+                    .next()
+                    .ok_or("No EGL config with OpenGL and pbuffers")?;
+                let context = unsafe { display.create_context(&config, &attributes) }
+                    .map_err(|e| format!("Cannot create an OpenGL 3.3 context: {e}"))?;
+                let size = std::num::NonZeroU32::MIN;
+                let surface_attributes =
+                    SurfaceAttributesBuilder::<PbufferSurface>::new().build(size, size);
+                let surface = unsafe { display.create_pbuffer_surface(&config, &surface_attributes) }
+                    .map_err(|e| format!("Cannot create a pbuffer: {e}"))?;
+                let context = context
+                    .make_current(&surface)
+                    .map_err(|e| format!("Cannot make the context current: {e}"))?;
+                (context, Some(surface))
+            }
+        };
+
+        let gl = unsafe {
+            glow::Context::from_loader_function_cstr(|name| display.get_proc_address(name))
+        };
+        Ok(Self {
+            gl,
+            _surface: surface,
+            _context: context,
+        })
+    }
+}
+
+/// First line of the error of a program that compiled but didn't link.
+const LINK_ERROR: &str = "Program link error:";
+
+/// Compiles and links a program without querying the result, so that with
+/// `GL_KHR_parallel_shader_compile` the driver can work in the background.
+unsafe fn submit_program(
+    gl: &glow::Context,
+    vertex_shader_source: &str,
+    fragment_shader_source: &str,
+) -> Result<ProgramJob, String> {
+    use glow::HasContext as _;
+
+    let program = gl
+        .create_program()
+        .map_err(|e| format!("Cannot create program: {e}"))?;
+    let mut shaders = Vec::with_capacity(2);
+    for (shader_type, source) in [
+        (glow::VERTEX_SHADER, vertex_shader_source),
+        (glow::FRAGMENT_SHADER, fragment_shader_source),
+    ] {
+        let shader = match gl.create_shader(shader_type) {
+            Ok(shader) => shader,
+            Err(e) => {
+                for shader in shaders {
+                    gl.delete_shader(shader);
+                }
+                gl.delete_program(program);
+                return Err(format!("Cannot create shader: {e}"));
+            }
+        };
+        gl.shader_source(shader, source);
+        gl.compile_shader(shader);
+        gl.attach_shader(program, shader);
+        shaders.push(shader);
+    }
+    gl.link_program(program);
+
+    Ok(ProgramJob {
+        program,
+        shaders: [shaders[0], shaders[1]],
+    })
+}
+
+/// Checks a submitted program, waiting for the driver if it isn't done.
+unsafe fn finish_program(gl: &glow::Context, job: ProgramJob) -> Result<glow::Program, String> {
+    use glow::HasContext as _;
+
+    let compile_error = job.shaders.iter().find_map(|&shader| {
+        (!gl.get_shader_compile_status(shader)).then(|| gl.get_shader_info_log(shader))
+    });
+    let result = match compile_error {
+        Some(log) => Err(format!("Shader compile error:\n{log}")),
+        None if !gl.get_program_link_status(job.program) => {
+            let log = gl.get_program_info_log(job.program);
+            Err(format!("{LINK_ERROR}\n{log}"))
+        }
+        None => Ok(job.program),
+    };
+
+    for shader in job.shaders {
+        gl.detach_shader(job.program, shader);
+        gl.delete_shader(shader);
+    }
+    if result.is_err() {
+        gl.delete_program(job.program);
+    }
+    result
+}
+
+unsafe fn discard_program(gl: &glow::Context, job: ProgramJob) {
+    use glow::HasContext as _;
+    for shader in job.shaders {
+        gl.delete_shader(shader);
+    }
+    gl.delete_program(job.program);
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Real log lines of each driver, with what they parse into.
+    const VENDOR_LOGS: &[(&str, &str, Option<usize>, Severity, &str)] = &[
+        (
+            "Mesa",
+            "0:14(10): error: `foo' undeclared",
+            Some(10),
+            Severity::Error,
+            "`foo' undeclared",
+        ),
+        (
+            "Mesa preprocessor",
+            "0:14(1): preprocessor error: syntax error, unexpected NEWLINE",
+            Some(1),
+            Severity::Error,
+            "syntax error, unexpected NEWLINE",
+        ),
+        (
+            "NVIDIA",
+            "0(14) : error C1008: undefined variable \"foo\"",
+            None,
+            Severity::Error,
+            "undefined variable \"foo\"",
+        ),
+        (
+            "NVIDIA warning",
+            "0(14) : warning C7050: \"v\" might be used before being initialized",
+            None,
+            Severity::Warning,
+            "\"v\" might be used before being initialized",
+        ),
+        (
+            "ANGLE",
+            "ERROR: 0:14: 'foo' : undeclared identifier",
+            None,
+            Severity::Error,
+            "'foo' : undeclared identifier",
+        ),
+        (
+            "ANGLE warning",
+            "WARNING: 0:14: 'GL_OES_standard_derivatives' : extension is not supported",
+            None,
+            Severity::Warning,
+            "'GL_OES_standard_derivatives' : extension is not supported",
+        ),
+    ];
+
+    #[test]
+    fn parses_every_vendor_format() {
+        for &(vendor, line, column, severity, message) in VENDOR_LOGS {
+            let parsed = parse_log_line(line);
+            assert_eq!(parsed, Some((14, column, severity, message.to_owned())), "{vendor}");
+        }
+    }
+
+    #[test]
+    fn other_log_lines_are_not_diagnostics() {
+        for line in ["", "Compile failed.", "ERROR: 1 compilation errors.  No code generated."] {
+            assert_eq!(parse_log_line(line), None, "{line:?}");
+        }
+    }
+
+    /// The snippet's `foo` line wrapped in tweet mode, and the compiled line
+    /// it ends up on.
+    fn wrapped_foo() -> (WrappedSource, usize) {
+        let mut snippet = MappedSource::default();
+        snippet.push_line("float a = 1.0;", 1);
+        snippet.push_line("o = vec4(foo);", 2);
+        let pass = wrapper::wrap_pass(&snippet, Some(ShaderMode::Tweet), GlslTarget::DESKTOP, "");
+        let source = pass.sources.into_iter().next().unwrap();
+        let line = source.header_lines + 2;
+        (source, line)
+    }
+
+    #[test]
+    fn remaps_every_vendor_through_the_wrapper() {
+        let (source, compiled_line) = wrapped_foo();
+        for &(vendor, line, column, severity, message) in VENDOR_LOGS {
+            let log = line.replacen("14", &compiled_line.to_string(), 1);
+            let log = format!("{log}\nCompile failed.");
+            let (text, diagnostics) = remap_log(&log, |line| source.snippet_line(line));
+            let expected = Diagnostic {
+                line: Some(2),
+                column,
+                severity,
+                message: message.to_owned(),
+            };
+            assert_eq!(text, format!("{expected}\nCompile failed."), "{vendor}");
+            assert_eq!(diagnostics, vec![expected], "{vendor}");
+        }
+    }
+
+    #[test]
+    fn header_lines_map_to_the_wrapper() {
+        let (source, _) = wrapped_foo();
+        let log = format!("0:{}(1): error: wrapper trouble", source.header_lines);
+        let (text, diagnostics) = remap_log(&log, |line| source.snippet_line(line));
+        assert_eq!(text, "wrapper: error: wrapper trouble");
+        assert_eq!(diagnostics[0].line, None);
+    }
+
+    #[test]
+    fn compile_progress_ranks_failures() {
+        let header = 10;
+        let early = compile_progress("0:12(1): error: early", header);
+        let late = compile_progress("0:15(1): error: late", header);
+        let syntax = compile_progress("0:20(1): error: syntax error, unexpected '}'", header);
+        let link = compile_progress(&format!("{LINK_ERROR}\nerror: no main"), header);
+        assert_eq!(early, (false, true, 2));
+        assert_eq!(late, (false, true, 5));
+        assert!(syntax < early && early < late && late < link);
+        // NVIDIA and ANGLE logs count the same way.
+        assert_eq!(compile_progress("0(15) : error C1008: late", header), late);
+        assert_eq!(compile_progress("ERROR: 0:15: 'x' : late", header), late);
+    }
+
+    fn failure(mode: ShaderMode, log: &str) -> ModeFailure {
+        ModeFailure {
+            mode,
+            progress: compile_progress(log, 0),
+            log: log.to_owned(),
+            diagnostics: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn mode_error_reports_the_furthest_mode() {
+        let err = mode_error(vec![
+            failure(ShaderMode::Tweet, "0:1(1): error: syntax error, unexpected '('"),
+            failure(ShaderMode::Shadertoy, "0:3(5): error: `foo' undeclared"),
+            failure(ShaderMode::Full, "0:3(5): error: `bar' undeclared"),
+        ]);
+        assert_eq!(err.mode, Some(ShaderMode::Shadertoy));
+        assert_eq!(err.message, "Shadertoy mode failed:\n0:3(5): error: `foo' undeclared");
+        let others: Vec<ShaderMode> = err.other_modes.iter().map(|(mode, _)| *mode).collect();
+        assert_eq!(others, [ShaderMode::Tweet, ShaderMode::Full]);
+    }
+}
@@ -1,17 +1,27 @@
+use std::collections::BTreeSet;
 use std::env;
 use std::fs::{self, File};
 use std::io::BufWriter;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
-use eframe::{egui, egui_glow, glow};
-use egui::mutex::Mutex;
-use egui_code_editor::{CodeEditor, ColorTheme, Syntax};
+use eframe::{egui, glow};
+use egui_code_editor::{CodeEditor, ColorTheme, Completer, Syntax};
 use gif::{Encoder as GifEncoder, Frame as GifFrame, Repeat};
 use rfd::FileDialog;
 
+#[cfg(target_os = "linux")]
+use shady::HeadlessGl;
+use shady::preview::{update_input, LiveShader};
+use shady::{
+    create_noise_texture, mode_pragma, set_channel_pragma, set_mode_pragma, split_passes,
+    write_png, write_sound_wav, ChannelConfig, ChannelSource, Diagnostic, FrameClock,
+    KeyboardInput, MouseInput, Severity, ShaderError, ShaderMode, ShaderOptions, ShaderState,
+    TextureFilter, TextureWrap, CHANNEL_NAMES, IMAGE_PASS, SOUND_PASS,
+};
+
 const DEFAULT_SNIPPET: &str = r"// Colorful warped waves
 vec2 uv = FC.xy / r.xy;
 uv.x *= r.x / r.y;
@@ -30,457 +40,495 @@ color.b = 0.5 + 0.5 * sin(uv.x + uv.y + time + 4.0);
 
 o = vec4(color, 1.0);";
 
-struct ShaderState {
-    program: glow::Program,
-    vertex_array: glow::VertexArray,
-    placeholder_texture: glow::Texture,
+/// Length of sound exports, in seconds, unless the CLI asks otherwise.
+const SOUND_EXPORT_SECONDS: f32 = 10.0;
+/// Frame size, frame rate and length of animation exports.
+const EXPORT_SIZE: [u32; 2] = [512, 512];
+const EXPORT_FPS: u32 = 30;
+const EXPORT_SECONDS: f32 = 3.0;
+
+/// Default pause in typing before the editor recompiles.
+const RECOMPILE_DELAY_MS: u32 = 300;
+
+/// GLSL keywords, qualifiers and preprocessor directives, for highlighting.
+const GLSL_KEYWORDS: &[&str] = &[
+    "attribute", "break", "case", "centroid", "const", "continue", "default", "discard", "do",
+    "else", "false", "flat", "for", "highp", "if", "in", "inout", "invariant", "layout", "lowp",
+    "mediump", "noperspective", "out", "precision", "return", "smooth", "struct", "switch",
+    "true", "uniform", "varying", "while",
+    // Preprocessor, after the `#`
+    "define", "defined", "elif", "endif", "extension", "ifdef", "ifndef", "pragma", "undef",
+    "version",
+];
+
+const GLSL_TYPES: &[&str] = &[
+    "void", "bool", "int", "uint", "float", "vec2", "vec3", "vec4", "bvec2", "bvec3", "bvec4",
+    "ivec2", "ivec3", "ivec4", "uvec2", "uvec3", "uvec4", "mat2", "mat3", "mat4", "mat2x2",
+    "mat2x3", "mat2x4", "mat3x2", "mat3x3", "mat3x4", "mat4x2", "mat4x3", "mat4x4", "sampler2D",
+    "sampler3D", "samplerCube", "sampler2DArray", "isampler2D", "usampler2D",
+];
+
+/// GLSL builtin functions and variables: name, signature and what it does,
+/// for highlighting, completion and the editor's hover docs.
+const GLSL_BUILTINS: &[(&str, &str, &str)] = &[
+    ("radians", "genType radians(genType degrees)", "Converts degrees to radians."),
+    ("degrees", "genType degrees(genType radians)", "Converts radians to degrees."),
+    ("sin", "genType sin(genType angle)", "Sine of an angle in radians."),
+    ("cos", "genType cos(genType angle)", "Cosine of an angle in radians."),
+    ("tan", "genType tan(genType angle)", "Tangent of an angle in radians."),
+    ("asin", "genType asin(genType x)", "Arc sine, in -π/2..π/2."),
+    ("acos", "genType acos(genType x)", "Arc cosine, in 0..π."),
+    (
+        "atan",
+        "genType atan(genType y, genType x)\ngenType atan(genType y_over_x)",
+        "Arc tangent. With two arguments the quadrant comes from their signs, in -π..π.",
+    ),
+    ("sinh", "genType sinh(genType x)", "Hyperbolic sine."),
+    ("cosh", "genType cosh(genType x)", "Hyperbolic cosine."),
+    ("tanh", "genType tanh(genType x)", "Hyperbolic tangent."),
+    ("pow", "genType pow(genType x, genType y)", "x raised to the power y. Undefined for x < 0."),
+    ("exp", "genType exp(genType x)", "Natural exponentiation, eˣ."),
+    ("log", "genType log(genType x)", "Natural logarithm."),
+    ("exp2", "genType exp2(genType x)", "2 raised to the power x."),
+    ("log2", "genType log2(genType x)", "Base 2 logarithm."),
+    ("sqrt", "genType sqrt(genType x)", "Square root."),
+    ("inversesqrt", "genType inversesqrt(genType x)", "1 / sqrt(x)."),
+    ("abs", "genType abs(genType x)", "Absolute value."),
+    ("sign", "genType sign(genType x)", "-1.0, 0.0 or 1.0 depending on the sign of x."),
+    ("floor", "genType floor(genType x)", "Nearest integer less than or equal to x."),
+    ("ceil", "genType ceil(genType x)", "Nearest integer greater than or equal to x."),
+    ("trunc", "genType trunc(genType x)", "x without its fractional part."),
+    ("round", "genType round(genType x)", "Nearest integer to x."),
+    ("fract", "genType fract(genType x)", "x - floor(x)."),
+    (
+        "mod",
+        "genType mod(genType x, genType y)\ngenType mod(genType x, float y)",
+        "x - y * floor(x / y).",
+    ),
+    (
+        "min",
+        "genType min(genType x, genType y)\ngenType min(genType x, float y)",
+        "Smaller of x and y.",
+    ),
+    (
+        "max",
+        "genType max(genType x, genType y)\ngenType max(genType x, float y)",
+        "Larger of x and y.",
+    ),
+    (
+        "clamp",
+        "genType clamp(genType x, genType minVal, genType maxVal)\ngenType clamp(genType x, float minVal, float maxVal)",
+        "min(max(x, minVal), maxVal).",
+    ),
+    (
+        "mix",
+        "genType mix(genType x, genType y, genType a)\ngenType mix(genType x, genType y, float a)",
+        "Linear blend of x and y: x * (1 - a) + y * a.",
+    ),
+    (
+        "step",
+        "genType step(genType edge, genType x)\ngenType step(float edge, genType x)",
+        "0.0 if x < edge, else 1.0.",
+    ),
+    (
+        "smoothstep",
+        "genType smoothstep(genType edge0, genType edge1, genType x)\ngenType smoothstep(float edge0, float edge1, genType x)",
+        "Hermite interpolation from 0.0 to 1.0 as x goes from edge0 to edge1.",
+    ),
+    ("isnan", "bvec isnan(genType x)", "Whether x is NaN."),
+    ("isinf", "bvec isinf(genType x)", "Whether x is infinite."),
+    ("length", "float length(genType x)", "Length of the vector x."),
+    ("distance", "float distance(genType p0, genType p1)", "Distance between p0 and p1."),
+    ("dot", "float dot(genType x, genType y)", "Dot product of x and y."),
+    ("cross", "vec3 cross(vec3 x, vec3 y)", "Cross product of x and y."),
+    ("normalize", "genType normalize(genType x)", "Vector in the direction of x with length 1."),
+    (
+        "faceforward",
+        "genType faceforward(genType N, genType I, genType Nref)",
+        "N if dot(Nref, I) < 0, else -N.",
+    ),
+    (
+        "reflect",
+        "genType reflect(genType I, genType N)",
+        "Reflection of I off a surface with normal N.",
+    ),
+    (
+        "refract",
+        "genType refract(genType I, genType N, float eta)",
+        "Refraction of I through a surface with normal N and ratio of indices eta.",
+    ),
+    (
+        "matrixCompMult",
+        "mat matrixCompMult(mat x, mat y)",
+        "Component-wise product of two matrices.",
+    ),
+    ("outerProduct", "mat outerProduct(vec c, vec r)", "Matrix product of column c and row r."),
+    ("transpose", "mat transpose(mat m)", "Transpose of m."),
+    ("determinant", "float determinant(mat m)", "Determinant of a square matrix."),
+    ("inverse", "mat inverse(mat m)", "Inverse of a square matrix."),
+    ("lessThan", "bvec lessThan(vec x, vec y)", "Component-wise x < y."),
+    ("lessThanEqual", "bvec lessThanEqual(vec x, vec y)", "Component-wise x <= y."),
+    ("greaterThan", "bvec greaterThan(vec x, vec y)", "Component-wise x > y."),
+    ("greaterThanEqual", "bvec greaterThanEqual(vec x, vec y)", "Component-wise x >= y."),
+    ("equal", "bvec equal(vec x, vec y)", "Component-wise x == y."),
+    ("notEqual", "bvec notEqual(vec x, vec y)", "Component-wise x != y."),
+    ("any", "bool any(bvec x)", "Whether any component of x is true."),
+    ("all", "bool all(bvec x)", "Whether all components of x are true."),
+    ("not", "bvec not(bvec x)", "Component-wise logical complement."),
+    (
+        "texture",
+        "vec4 texture(sampler2D sampler, vec2 P)\nvec4 texture(sampler2D sampler, vec2 P, float bias)",
+        "Samples a texture at normalized coordinates P.",
+    ),
+    (
+        "textureLod",
+        "vec4 textureLod(sampler2D sampler, vec2 P, float lod)",
+        "Samples a texture at an explicit mip level.",
+    ),
+    (
+        "textureGrad",
+        "vec4 textureGrad(sampler2D sampler, vec2 P, vec2 dPdx, vec2 dPdy)",
+        "Samples a texture with explicit derivatives.",
+    ),
+    (
+        "texelFetch",
+        "vec4 texelFetch(sampler2D sampler, ivec2 P, int lod)",
+        "Reads one texel at integer coordinates P.",
+    ),
+    (
+        "textureSize",
+        "ivec2 textureSize(sampler2D sampler, int lod)",
+        "Size in texels of a mip level.",
+    ),
+    ("dFdx", "genType dFdx(genType p)", "Derivative of p in x across neighbouring fragments."),
+    ("dFdy", "genType dFdy(genType p)", "Derivative of p in y across neighbouring fragments."),
+    ("fwidth", "genType fwidth(genType p)", "abs(dFdx(p)) + abs(dFdy(p))."),
+    ("floatBitsToInt", "genIType floatBitsToInt(genType value)", "Bits of a float as an int."),
+    ("floatBitsToUint", "genUType floatBitsToUint(genType value)", "Bits of a float as a uint."),
+    ("intBitsToFloat", "genType intBitsToFloat(genIType value)", "Float with the bits of an int."),
+    (
+        "uintBitsToFloat",
+        "genType uintBitsToFloat(genUType value)",
+        "Float with the bits of a uint.",
+    ),
+    ("gl_FragCoord", "in vec4 gl_FragCoord", "Window coordinates of the fragment, in pixels."),
+];
+
+/// Syntax definition of the code editor. The special words are the builtins
+/// and the uniforms of `mode`.
+fn glsl_syntax(mode: ShaderMode) -> Syntax {
+    let language = match mode {
+        ShaderMode::Tweet => "GLSL (tweet)",
+        ShaderMode::Shadertoy => "GLSL (Shadertoy)",
+        ShaderMode::Full => "GLSL",
+    };
+    Syntax {
+        language,
+        case_sensitive: true,
+        comment: "//",
+        comment_multiline: ["/*", "*/"],
+        hyperlinks: BTreeSet::from(["http"]),
+        keywords: GLSL_KEYWORDS.iter().copied().collect(),
+        types: GLSL_TYPES.iter().copied().collect(),
+        special: GLSL_BUILTINS
+            .iter()
+            .chain(mode.uniforms())
+            .map(|&(name, _, _)| name)
+            .collect(),
+    }
 }
 
-impl ShaderState {
-    fn new(gl: &glow::Context, snippet: &str) -> Result<Self, String> {
-        let (shader_version, precision_line) = if cfg!(target_arch = "wasm32") {
-            ("#version 300 es", "precision mediump float;")
-        } else {
-            ("#version 330 core", "")
-        };
+/// Signature and description of a builtin or a uniform of `mode`.
+fn glsl_doc(word: &str, mode: ShaderMode) -> Option<(&'static str, &'static str)> {
+    GLSL_BUILTINS
+        .iter()
+        .chain(mode.uniforms())
+        .find(|&&(name, _, _)| name == word)
+        .map(|&(_, signature, doc)| (signature, doc))
+}
 
-        let vertex_shader_source = format!(
-            "{shader_version}\n{}",
-            r#"
-            const vec2 verts[3] = vec2[3](
-                vec2(-1.0, -1.0),
-                vec2(3.0, -1.0),
-                vec2(-1.0, 3.0)
-            );
+/// Shows the signature of the builtin or uniform under the pointer.
+fn show_glsl_docs(
+    ui: &mut egui::Ui,
+    output: &egui::text_edit::TextEditOutput,
+    visible: egui::Rect,
+    snippet: &str,
+    mode: ShaderMode,
+) {
+    let Some(pointer) = ui.ctx().pointer_hover_pos() else {
+        return;
+    };
+    if !output.response.hovered() || !output.text_clip_rect.contains(pointer) {
+        return;
+    }
 
-            void main() {
-                gl_Position = vec4(verts[gl_VertexID], 0.0, 1.0);
-            }
-        "#
-        );
-        // Build both variants up front.
-        // Tweet-style body that writes to `o` and uses FC, r, t.
-        let tweet_fragment_body = format!(
-            r#"
-            {precision_line}
-            uniform vec2 r;
-            uniform float t;
-            uniform vec2 rect_min;
-            out vec4 fragColor;
-
-            void main() {{
-                vec2 FC = gl_FragCoord.xy - rect_min;
-                vec4 o = vec4(0.0);
-                {snippet}
-                fragColor = o;
-            }}
-        "#
-        );
+    let galley_offset = output.galley_pos.to_vec2();
+    let chars: Vec<char> = snippet.chars().collect();
+    let index = output
+        .galley
+        .cursor_from_pos(pointer - output.galley_pos)
+        .index
+        .min(chars.len());
+    let is_word = |c: &&char| c.is_ascii_alphanumeric() || **c == '_';
+    let start = index - chars[..index].iter().rev().take_while(is_word).count();
+    let end = index + chars[index..].iter().take_while(is_word).count();
+    let word: String = chars[start..end].iter().collect();
+    let Some((signature, doc)) = glsl_doc(&word, mode) else {
+        return;
+    };
 
-        let tweet_fragment_source = format!("{shader_version}\n{tweet_fragment_body}");
-
-        // Shadertoy fragment shader variant.
-        let shadertoy_fragment_body = {
-            let body = r#"
-            uniform float iTime;
-            uniform vec3 iResolution;
-            uniform vec4 iMouse;
-            uniform int iFrame;
-            uniform float iChannelTime[4];
-            uniform vec3 iChannelResolution[4];
-            uniform sampler2D iChannel0;
-            uniform sampler2D iChannel1;
-            uniform sampler2D iChannel2;
-            uniform sampler2D iChannel3;
-            uniform vec2 rect_min;
-            out vec4 fragColor;
-
-            // Compatibility shim: some Shadertoy shaders sample 3D/4D coords
-            // even when the channel is a 2D sampler. Drop extra components so they
-            // still compile. Provide overloads that forward to textureLod.
-            vec4 texture(sampler2D tex, vec3 uvw) { return textureLod(tex, uvw.xy, 0.0); }
-            vec4 texture(sampler2D tex, vec4 uvw) { return textureLod(tex, uvw.xy, 0.0); }
-
-            void mainImage(out vec4 fragColor, in vec2 fragCoord);
-
-            void main() {
-                vec4 color = vec4(0.0);
-                mainImage(color, gl_FragCoord.xy - rect_min);
-                fragColor = color;
-            }
-        "#;
-            format!("{precision_line}\n{body}")
-        };
+    let cursor_rect = |index: usize| {
+        output
+            .galley
+            .pos_from_cursor(egui::text::CCursor::new(index))
+            .translate(galley_offset)
+    };
+    let (left, right) = (cursor_rect(start), cursor_rect(end));
+    let token = egui::Rect::from_min_max(left.min, egui::pos2(right.left(), left.bottom()));
+    if !token.contains(pointer) || !visible.contains(pointer) {
+        return;
+    }
+    ui.interact(token, ui.id().with(("glsl_doc", start)), egui::Sense::hover())
+        .on_hover_ui(|ui| {
+            ui.label(egui::RichText::new(signature).monospace());
+            ui.label(doc);
+        });
+}
 
-        // Preprocess the snippet for Shadertoy mode to avoid conflicts between
-        // rgb/grgb macros and .rgb/.grb swizzles on some drivers. We rename the
-        // macros and all of their call sites, but leave swizzles untouched.
-        let shadertoy_snippet = {
-            let mut s = snippet.to_owned();
-            s = s.replace("#define rgb(", "#define rgbx(");
-            s = s.replace("rgb(", "rgbx(");
-            s = s.replace("#define grgb(", "#define grgbx(");
-            s = s.replace("grgb(", "grgbx(");
-            s
-        };
+/// Char index of `column` (1-based) on `line` (1-based) of `text`, clamped
+/// to the line.
+fn char_index(text: &str, line: usize, column: usize) -> usize {
+    let mut index = 0;
+    for (i, content) in text.split('\n').enumerate() {
+        let len = content.chars().count();
+        if i + 1 == line {
+            return index + column.saturating_sub(1).min(len);
+        }
+        index += len + 1;
+    }
+    index
+}
 
-        let shadertoy_fragment_source =
-            format!("{shader_version}\n{shadertoy_fragment_body}\n{shadertoy_snippet}");
+/// Chars of `line` that a diagnostic at `column` (1-based) is about: the
+/// identifier or number there, or the single char. Drivers sometimes point
+/// at the space or bracket just before the token, so those are skipped.
+fn token_span(line: &str, column: usize) -> (usize, usize) {
+    let chars: Vec<char> = line.chars().collect();
+    let is_word = |c: char| c.is_alphanumeric() || c == '_' || c == '.';
+    let mut start = column.saturating_sub(1).min(chars.len());
+    while start < chars.len() && matches!(chars[start], ' ' | '\t' | '(' | '[' | ',') {
+        start += 1;
+    }
+    let mut end = start;
+    while end < chars.len() && is_word(chars[end]) {
+        end += 1;
+    }
+    if end == start {
+        end = (start + 1).min(chars.len());
+    }
+    (start, end)
+}
 
-        // Full GLSL fragment shader variant.
-        let full_fragment_source = if snippet.contains("#version") {
-            snippet.to_owned()
-        } else if precision_line.is_empty() {
-            format!("{shader_version}\n{snippet}")
-        } else {
-            format!("{shader_version}\n{precision_line}\n{snippet}")
-        };
+/// Marks the lines of `diagnostics` in the code editor: the gutter is
+/// tinted, the token at the reported column gets a squiggle, and hovering
+/// either shows the messages. `visible` is the editor's viewport.
+fn paint_diagnostics(
+    ui: &mut egui::Ui,
+    output: &egui::text_edit::TextEditOutput,
+    visible: egui::Rect,
+    snippet: &str,
+    diagnostics: &[Diagnostic],
+) {
+    let painter = ui.painter_at(visible);
+    let galley_offset = output.galley_pos.to_vec2();
+    let cursor_rect = |index: usize| {
+        output
+            .galley
+            .pos_from_cursor(egui::text::CCursor::new(index))
+            .translate(galley_offset)
+    };
+    let lines: Vec<&str> = snippet.split('\n').collect();
 
-        // Heuristic: if the snippet looks like a Shadertoy shader (has
-        // `mainImage` or `iTime`/`iResolution`), try Shadertoy mode first.
-        // Otherwise, if it looks like a complete GLSL shader (has `void main`,
-        // `#version`, or explicit outputs), try full mode first; otherwise
-        // prefer tweet mode first. On failure, fall back to the other modes.
-        let looks_like_shadertoy = {
-            let s = snippet;
-            s.contains("mainImage") || s.contains("iTime") || s.contains("iResolution")
+    let mut marked: Vec<usize> = Vec::new();
+    for diagnostic in diagnostics {
+        let Some(line) = diagnostic.line.filter(|&l| l <= lines.len()) else {
+            continue;
         };
-        let looks_like_full = {
-            let s = snippet;
-            s.contains("void main")
-                || s.contains("#version")
-                || s.contains("gl_FragColor")
-                || s.contains("out vec4")
+        let color = match diagnostic.severity {
+            Severity::Error => egui::Color32::from_rgb(220, 80, 80),
+            Severity::Warning => egui::Color32::from_rgb(220, 180, 60),
         };
-
-        unsafe {
-            let try_shadertoy = || {
-                Self::create_program(gl, &vertex_shader_source, &shadertoy_fragment_source)
-            };
-            let try_full = || Self::create_program(gl, &vertex_shader_source, &full_fragment_source);
-            let try_tweet =
-                || Self::create_program(gl, &vertex_shader_source, &tweet_fragment_source);
-
-            let result = if looks_like_shadertoy {
-                try_shadertoy()
-                    .or_else(|shadertoy_err| {
-                        try_full().or_else(|full_err| {
-                            try_tweet().map_err(|tweet_err| {
-                                format!(
-                                    "Shadertoy mode failed:\n{}\n\nFull GLSL mode also failed:\n{}\n\nTweet shader mode also failed:\n{}",
-                                    shadertoy_err, full_err, tweet_err
-                                )
-                            })
-                        })
-                    })
-            } else if looks_like_full {
-                try_full().or_else(|full_err| {
-                    try_shadertoy().or_else(|shadertoy_err| {
-                        try_tweet().map_err(|tweet_err| {
-                            format!(
-                                "Full GLSL mode failed:\n{}\n\nShadertoy mode also failed:\n{}\n\nTweet shader mode also failed:\n{}",
-                                full_err, shadertoy_err, tweet_err
-                            )
-                        })
-                    })
-                })
-            } else {
-                try_tweet().or_else(|tweet_err| {
-                    try_shadertoy().or_else(|shadertoy_err| {
-                        try_full().map_err(|full_err| {
-                            format!(
-                                "Tweet shader mode failed:\n{}\n\nShadertoy mode also failed:\n{}\n\nFull GLSL mode also failed:\n{}",
-                                tweet_err, shadertoy_err, full_err
-                            )
-                        })
-                    })
-                })
-            }?;
-
-            // Create a small procedural noise texture for iChannel* uniforms.
-            let placeholder_texture = {
-                use glow::HasContext as _;
-                let tex = gl
-                    .create_texture()
-                    .map_err(|e| format!("Cannot create texture: {e}"))?;
-                gl.bind_texture(glow::TEXTURE_2D, Some(tex));
-
-                let size: i32 = 64;
-                let mut data = vec![0u8; (size * size * 4) as usize];
-                for y in 0..size {
-                    for x in 0..size {
-                        let idx = ((y * size + x) * 4) as usize;
-                        let mut seed = (x as u32)
-                            .wrapping_mul(1973)
-                            ^ (y as u32).wrapping_mul(9277)
-                            ^ 0x7feb_352d;
-                        seed = seed.wrapping_mul(0x27d4_eb2d);
-                        let v = (seed >> 24) as u8;
-                        data[idx] = v;
-                        data[idx + 1] = v;
-                        data[idx + 2] = v;
-                        data[idx + 3] = 255;
-                    }
-                }
-
-                gl.tex_image_2d(
-                    glow::TEXTURE_2D,
-                    0,
-                    glow::RGBA8 as i32,
-                    size,
-                    size,
-                    0,
-                    glow::RGBA,
-                    glow::UNSIGNED_BYTE,
-                    glow::PixelUnpackData::Slice(Some(&data)),
-                );
-                gl.tex_parameter_i32(
-                    glow::TEXTURE_2D,
-                    glow::TEXTURE_MIN_FILTER,
-                    glow::NEAREST as i32,
-                );
-                gl.tex_parameter_i32(
-                    glow::TEXTURE_2D,
-                    glow::TEXTURE_MAG_FILTER,
-                    glow::NEAREST as i32,
-                );
-                gl.bind_texture(glow::TEXTURE_2D, None);
-                tex
-            };
-
-            Ok(Self {
-                program: result.program,
-                vertex_array: result.vertex_array,
-                placeholder_texture,
-            })
+        let row = cursor_rect(char_index(snippet, line, 1));
+        if !visible.intersects(row) {
+            continue;
         }
-    }
 
-    unsafe fn create_program(
-        gl: &glow::Context,
-        vertex_shader_source: &str,
-        fragment_shader_source: &str,
-    ) -> Result<Self, String> {
-        use glow::HasContext as _;
-
-        let program = gl
-            .create_program()
-            .map_err(|e| format!("Cannot create program: {e}"))?;
-
-        let vs = compile_shader(gl, glow::VERTEX_SHADER, vertex_shader_source).map_err(|e| {
-            gl.delete_program(program);
-            e
-        })?;
-        let fs = compile_shader(gl, glow::FRAGMENT_SHADER, fragment_shader_source).map_err(|e| {
-            gl.delete_shader(vs);
-            gl.delete_program(program);
-            e
-        })?;
-
-        gl.attach_shader(program, vs);
-        gl.attach_shader(program, fs);
-
-        gl.link_program(program);
-        if !gl.get_program_link_status(program) {
-            let log = gl.get_program_info_log(program);
-            gl.delete_shader(vs);
-            gl.delete_shader(fs);
-            gl.delete_program(program);
-            return Err(format!("Program link error:\n{log}"));
+        // Every message of the line, for the hover text
+        let messages = diagnostics
+            .iter()
+            .filter(|d| d.line == Some(line))
+            .map(|d| d.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        if !marked.contains(&line) {
+            marked.push(line);
+            let gutter = egui::Rect::from_x_y_ranges(
+                visible.left()..=output.text_clip_rect.left(),
+                row.y_range(),
+            );
+            painter.rect_filled(gutter, 0.0, color.gamma_multiply(0.35));
+            let text_row =
+                egui::Rect::from_x_y_ranges(output.text_clip_rect.x_range(), row.y_range());
+            painter.rect_filled(text_row, 0.0, color.gamma_multiply(0.08));
+            ui.interact(gutter, ui.id().with(("diagnostic_gutter", line)), egui::Sense::hover())
+                .on_hover_text(&messages);
         }
 
-        gl.detach_shader(program, vs);
-        gl.detach_shader(program, fs);
-        gl.delete_shader(vs);
-        gl.delete_shader(fs);
-
-        let vertex_array = gl
-            .create_vertex_array()
-            .map_err(|e| format!("Cannot create vertex array: {e}"))?;
-
-        Ok(Self { program, vertex_array, placeholder_texture: gl.create_texture().unwrap() })
-    }
-
-    fn paint(
-        &self,
-        gl: &glow::Context,
-        time: f32,
-        rect_min: egui::Pos2,
-        resolution: egui::Vec2,
-        mouse_pos: Option<egui::Vec2>,
-    ) {
-        use glow::HasContext as _;
-        unsafe {
-            gl.clear_color(0.0, 0.0, 0.0, 1.0);
-            gl.clear(glow::COLOR_BUFFER_BIT);
-
-            gl.use_program(Some(self.program));
-
-            if let Some(loc) = gl.get_uniform_location(self.program, "t") {
-                gl.uniform_1_f32(Some(&loc), time);
-            }
-            if let Some(loc) = gl.get_uniform_location(self.program, "r") {
-                gl.uniform_2_f32(Some(&loc), resolution.x, resolution.y);
-            }
-            if let Some(loc) = gl.get_uniform_location(self.program, "rect_min") {
-                gl.uniform_2_f32(Some(&loc), rect_min.x, rect_min.y);
-            }
-
-            // Shadertoy uniforms
-            if let Some(loc) = gl.get_uniform_location(self.program, "iTime") {
-                gl.uniform_1_f32(Some(&loc), time);
-            }
-            if let Some(loc) = gl.get_uniform_location(self.program, "iResolution") {
-                gl.uniform_3_f32(Some(&loc), resolution.x, resolution.y, 1.0);
-            }
-            if let Some(loc) = gl.get_uniform_location(self.program, "iMouse") {
-                let (x, y, z, w) = if let Some(mouse) = mouse_pos {
-                    let mx = mouse.x;
-                    let my = resolution.y - mouse.y;
-                    (mx, my, mx, my)
-                } else {
-                    (0.0, 0.0, 0.0, 0.0)
-                };
-                gl.uniform_4_f32(Some(&loc), x, y, z, w);
-            }
-            if let Some(loc) = gl.get_uniform_location(self.program, "iFrame") {
-                let frame = (time * 60.0).floor() as i32;
-                gl.uniform_1_i32(Some(&loc), frame);
-            }
-            if let Some(loc) = gl.get_uniform_location(self.program, "iChannelTime") {
-                gl.uniform_1_f32_slice(Some(&loc), &[time, time, time, time]);
-            }
-            if let Some(loc) = gl.get_uniform_location(self.program, "iChannelResolution") {
-                let data = [
-                    resolution.x,
-                    resolution.y,
-                    1.0,
-                    resolution.x,
-                    resolution.y,
-                    1.0,
-                    resolution.x,
-                    resolution.y,
-                    1.0,
-                    resolution.x,
-                    resolution.y,
-                    1.0,
-                ];
-                gl.uniform_3_f32_slice(Some(&loc), &data);
-            }
-            // Bind placeholder textures for iChannel0-3
-            for (i, name) in ["iChannel0", "iChannel1", "iChannel2", "iChannel3"]
-                .iter()
-                .enumerate()
-            {
-                gl.active_texture(glow::TEXTURE0 + i as u32);
-                gl.bind_texture(glow::TEXTURE_2D, Some(self.placeholder_texture));
-                if let Some(loc) = gl.get_uniform_location(self.program, name) {
-                    gl.uniform_1_i32(Some(&loc), i as i32);
-                }
+        if let Some(column) = diagnostic.column {
+            let (start, end) = token_span(lines[line - 1], column);
+            let line_start = char_index(snippet, line, 1);
+            let left = cursor_rect(line_start + start).left();
+            let right = cursor_rect(line_start + end).left().max(left + 4.0);
+            let y = row.bottom() - 1.5;
+
+            // Zig-zag underline
+            let step = 2.0;
+            let mut points = Vec::new();
+            let mut x = left;
+            let mut up = false;
+            while x <= right {
+                points.push(egui::pos2(x, if up { y - step } else { y }));
+                x += step;
+                up = !up;
             }
+            painter.add(egui::Shape::line(points, egui::Stroke::new(1.2, color)));
 
-            gl.bind_vertex_array(Some(self.vertex_array));
-            gl.draw_arrays(glow::TRIANGLES, 0, 3);
+            let token = egui::Rect::from_x_y_ranges(left..=right, row.y_range());
+            ui.interact(
+                token,
+                ui.id().with(("diagnostic_token", line, column)),
+                egui::Sense::hover(),
+            )
+            .on_hover_text(&messages);
         }
     }
+}
 
-    fn render_to_image(
-        &self,
-        gl: &glow::Context,
-        time: f32,
-        size: [u32; 2],
-    ) -> Result<Vec<u8>, String> {
-        use glow::HasContext as _;
-
-        let width = size[0] as i32;
-        let height = size[1] as i32;
-
-        unsafe {
-            let framebuffer = gl
-                .create_framebuffer()
-                .map_err(|e| format!("Failed to create framebuffer: {e}"))?;
-            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(framebuffer));
-
-            let texture = gl
-                .create_texture()
-                .map_err(|e| format!("Failed to create texture: {e}"))?;
-            gl.bind_texture(glow::TEXTURE_2D, Some(texture));
-            gl.tex_image_2d(
-                glow::TEXTURE_2D,
-                0,
-                glow::RGBA8 as i32,
-                width,
-                height,
-                0,
-                glow::RGBA,
-                glow::UNSIGNED_BYTE,
-                glow::PixelUnpackData::BufferOffset(0),
-            );
-            gl.tex_parameter_i32(
-                glow::TEXTURE_2D,
-                glow::TEXTURE_MIN_FILTER,
-                glow::LINEAR as i32,
-            );
-            gl.tex_parameter_i32(
-                glow::TEXTURE_2D,
-                glow::TEXTURE_MAG_FILTER,
-                glow::LINEAR as i32,
-            );
-            gl.framebuffer_texture_2d(
-                glow::FRAMEBUFFER,
-                glow::COLOR_ATTACHMENT0,
-                glow::TEXTURE_2D,
-                Some(texture),
-                0,
-            );
-
-            if gl.check_framebuffer_status(glow::FRAMEBUFFER) != glow::FRAMEBUFFER_COMPLETE {
-                gl.bind_framebuffer(glow::FRAMEBUFFER, None);
-                gl.delete_texture(texture);
-                gl.delete_framebuffer(framebuffer);
-                return Err("Framebuffer is not complete".to_owned());
-            }
-
-            gl.viewport(0, 0, width, height);
+/// Moves the code editor's cursor to `line` and `column` (1-based), focuses
+/// it and scrolls the line into the middle of the viewport.
+fn jump_to_line(
+    ui: &mut egui::Ui,
+    output: &egui::text_edit::TextEditOutput,
+    visible: egui::Rect,
+    snippet: &str,
+    line: usize,
+    column: Option<usize>,
+) {
+    let index = char_index(snippet, line, column.unwrap_or(1));
+    let cursor = egui::text::CCursor::new(index);
+
+    let mut state = output.state.clone();
+    state
+        .cursor
+        .set_char_range(Some(egui::text::CCursorRange::one(cursor)));
+    state.store(ui.ctx(), output.response.id);
+    ui.memory_mut(|m| m.request_focus(output.response.id));
+
+    // The editor's vertical scroll area, see `CodeEditor::show`.
+    let scroll_id = ui.make_persistent_id(egui::Id::new("shader_code_editor_outer_scroll"));
+    if let Some(mut scroll) = egui::scroll_area::State::load(ui.ctx(), scroll_id) {
+        let row = output.galley.pos_from_cursor(cursor);
+        let galley_top = output.galley_pos.y + scroll.offset.y;
+        let row_top = galley_top - visible.top() + row.top();
+        scroll.offset.y = (row_top - visible.height() / 2.0).max(0.0);
+        scroll.store(ui.ctx(), scroll_id);
+    }
+}
 
-            self.paint(
-                gl,
-                time,
-                egui::Pos2::new(0.0, 0.0),
-                egui::vec2(width as f32, height as f32),
-                None,
-            );
+/// Runs `job` on an OpenGL context for the CLI: a headless one where EGL is
+/// available, else the context of a short-lived eframe window.
+fn with_cli_gl<T>(job: impl FnOnce(&glow::Context) -> T) -> Result<T, String> {
+    #[cfg(target_os = "linux")]
+    let headless_error = match HeadlessGl::new() {
+        Ok(headless) => return Ok(job(&headless.gl)),
+        Err(err) => err,
+    };
 
-            let mut pixels = vec![0u8; (width * height * 4) as usize];
-            gl.read_pixels(
-                0,
-                0,
-                width,
-                height,
-                glow::RGBA,
-                glow::UNSIGNED_BYTE,
-                glow::PixelPackData::Slice(Some(pixels.as_mut_slice())),
-            );
+    let mut result = None;
+    let native_options = eframe::NativeOptions {
+        renderer: eframe::Renderer::Glow,
+        ..Default::default()
+    };
+    let run_result = eframe::run_native(
+        "Shady CLI compile",
+        native_options,
+        Box::new(|cc| {
+            Ok(Box::new(CliCompileApp::new(cc, |gl: &glow::Context| {
+                result = Some(job(gl));
+            })))
+        }),
+    );
+
+    match (run_result, result) {
+        (_, Some(result)) => Ok(result),
+        #[cfg(target_os = "linux")]
+        (Err(e), None) => Err(format!("{headless_error}; no window either: {e}")),
+        #[cfg(not(target_os = "linux"))]
+        (Err(e), None) => Err(e.to_string()),
+        (Ok(()), None) => Err("The window closed before the shader ran".to_owned()),
+    }
+}
 
-            gl.bind_texture(glow::TEXTURE_2D, None);
-            gl.bind_framebuffer(glow::FRAMEBUFFER, None);
-            gl.delete_texture(texture);
-            gl.delete_framebuffer(framebuffer);
+/// A shader the CLI built: the mode it's in, and the logs of the modes tried
+/// before it.
+struct BuiltShader {
+    mode: ShaderMode,
+    failed_modes: Vec<(ShaderMode, String)>,
+}
 
-            Ok(pixels)
+impl BuiltShader {
+    fn of(shader: &ShaderState) -> Self {
+        Self {
+            mode: shader.mode(),
+            failed_modes: shader.failed_modes().to_vec(),
         }
     }
 }
 
-struct CliCompileApp {
+/// Compiles a shader for the CLI, and renders its Sound pass to a WAV file
+/// when `sound_export` is set.
+fn compile_job(
+    gl: &glow::Context,
+    snippet: &str,
+    options: &ShaderOptions,
+    sound_export: Option<&(PathBuf, f32)>,
+) -> Result<BuiltShader, ShaderError> {
+    let shader = ShaderState::new(gl, snippet, options)?;
+    let built = BuiltShader::of(&shader);
+    let exported = match sound_export {
+        Some((path, seconds)) => shader
+            .render_sound(gl, *seconds)
+            .and_then(|samples| write_sound_wav(path, &samples))
+            .map_err(ShaderError::from),
+        None => Ok(()),
+    };
+    shader.destroy(gl);
+    exported.map(|()| built)
+}
+
+/// Work the CLI does with a GL context.
+type CliJob<'a> = Box<dyn FnOnce(&glow::Context) + 'a>;
+
+/// Window the CLI falls back to without headless GL: it runs its job on the
+/// first frame and closes.
+struct CliCompileApp<'a> {
     gl: Arc<glow::Context>,
-    snippet: String,
-    result: Arc<Mutex<Option<String>>>,
-    compiled: bool,
+    job: Option<CliJob<'a>>,
 }
 
-impl CliCompileApp {
-    fn new(cc: &eframe::CreationContext<'_>, snippet: String, result: Arc<Mutex<Option<String>>>) -> Self {
+impl<'a> CliCompileApp<'a> {
+    fn new(cc: &eframe::CreationContext<'_>, job: impl FnOnce(&glow::Context) + 'a) -> Self {
         let gl = cc
             .gl
             .as_ref()
@@ -489,54 +537,88 @@ impl CliCompileApp {
 
         Self {
             gl,
-            snippet,
-            result,
-            compiled: false,
+            job: Some(Box::new(job)),
         }
     }
 }
 
-impl eframe::App for CliCompileApp {
+impl eframe::App for CliCompileApp<'_> {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        if self.compiled {
-            // Request the window to close once we've already compiled.
-            ctx.send_viewport_cmd(egui::ViewportCommand::Close);
-            return;
+        if let Some(job) = self.job.take() {
+            job(&self.gl);
         }
 
-        let compile_result = ShaderState::new(&self.gl, &self.snippet);
-        let mut lock = self.result.lock();
-        *lock = compile_result.err();
-        self.compiled = true;
-
-        // Close after first compile attempt so run_native can return.
+        // Close after the first frame so run_native can return.
         ctx.send_viewport_cmd(egui::ViewportCommand::Close);
     }
 }
 
-unsafe fn compile_shader(
-    gl: &glow::Context,
-    shader_type: u32,
-    source: &str,
-) -> Result<glow::Shader, String> {
-    use glow::HasContext as _;
-    let shader = gl
-        .create_shader(shader_type)
-        .map_err(|e| format!("Cannot create shader: {e}"))?;
-    gl.shader_source(shader, source);
-    gl.compile_shader(shader);
-    if !gl.get_shader_compile_status(shader) {
-        let log = gl.get_shader_info_log(shader);
-        gl.delete_shader(shader);
-        Err(format!("Shader compile error:\n{log}"))
-    } else {
-        Ok(shader)
+/// Creates a looping GIF file for frames of `size`.
+fn create_gif_encoder(path: &Path, size: [u32; 2]) -> Result<GifEncoder<BufWriter<File>>, String> {
+    let file = File::create(path).map_err(|e| format!("Failed to create GIF file: {e}"))?;
+    let writer = BufWriter::new(file);
+    let mut encoder = GifEncoder::new(writer, size[0] as u16, size[1] as u16, &[])
+        .map_err(|e| format!("Failed to create GIF encoder: {e}"))?;
+    encoder
+        .set_repeat(Repeat::Infinite)
+        .map_err(|e| format!("Failed to set GIF repeat: {e}"))?;
+    Ok(encoder)
+}
+
+/// GIF delay of frame `index` of `frame_count` at `fps` in centiseconds, or
+/// `None` when the frame is left out.
+fn gif_frame_delay(index: u32, frame_count: u32, fps: u32) -> Option<u16> {
+    // GIF delays are whole centiseconds, so round each frame's end time
+    // rather than its length, keeping the total duration exact.
+    let centiseconds = |frame: u32| u64::from(frame) * 100 / u64::from(fps);
+    let total = centiseconds(frame_count);
+    // Players stretch delays under 2 cs to 10 cs, so above 50 fps frames end
+    // on even centiseconds, at least 2 before the last one ends, and those
+    // ending with the previous frame are dropped.
+    let end = |frame: u32| {
+        let end = centiseconds(frame + 1);
+        if fps <= 50 || frame + 1 >= frame_count {
+            end
+        } else {
+            end.min(total.saturating_sub(2)) / 2 * 2
+        }
+    };
+    let start = index.checked_sub(1).map_or(0, end);
+    match end(index) - start {
+        0 => None,
+        delay => Some(delay.max(2) as u16),
     }
 }
 
+/// Renders frame `index` of an animation of `frame_count` frames at `fps`
+/// and appends it to a GIF, unless its delay leaves it out.
+fn write_gif_frame(
+    encoder: &mut GifEncoder<BufWriter<File>>,
+    shader: &mut ShaderState,
+    gl: &glow::Context,
+    index: u32,
+    frame_count: u32,
+    fps: u32,
+    size: [u32; 2],
+) -> Result<(), String> {
+    // Dropped frames still render, so buffer passes see every frame.
+    let clock = FrameClock::fixed(index, fps);
+    let mut rgba = shader.render_to_image(gl, &clock, size)?;
+    let Some(delay) = gif_frame_delay(index, frame_count, fps) else {
+        return Ok(());
+    };
+
+    let mut frame = GifFrame::from_rgba_speed(size[0] as u16, size[1] as u16, &mut rgba, 10);
+    frame.delay = delay;
+
+    encoder
+        .write_frame(&frame)
+        .map_err(|e| format!("Failed to write GIF frame: {e}"))
+}
+
 struct GifExportState {
     encoder: GifEncoder<BufWriter<File>>,
-    shader: Arc<Mutex<ShaderState>>,
+    shader: ShaderState,
     frame_index: u32,
     frame_count: u32,
     width: u32,
@@ -548,10 +630,32 @@ struct ShadyApp {
     gl: Arc<glow::Context>,
     snippet: String,
     last_error: Option<String>,
-    shader: Option<Arc<Mutex<ShaderState>>>,
-    start_time: Instant,
+    /// Compiler messages of the last failed compile, in snippet lines.
+    diagnostics: Vec<Diagnostic>,
+    /// Errors of the modes that weren't the likely intent, folded away.
+    other_mode_errors: Vec<(ShaderMode, String)>,
+    /// Line and column the editor should move its cursor to next frame.
+    jump_to: Option<(usize, Option<usize>)>,
+    /// Shader on screen, the last one that compiled, and the one compiling
+    /// in the background while it keeps drawing.
+    live: LiveShader,
+    mouse: MouseInput,
+    keyboard: KeyboardInput,
     needs_recompile: bool,
+    /// When the snippet was last edited, until the edit is compiled.
+    edited_at: Option<Instant>,
+    /// How long typing has to pause before an edit is compiled.
+    recompile_delay_ms: u32,
+    /// Noise texture shared by every shader the app compiles.
+    noise_texture: Option<glow::Texture>,
+    /// Mode the editor highlights, completes and documents the uniforms of.
+    editor_mode: ShaderMode,
+    syntax: Syntax,
+    completer: Completer,
     gif_export: Option<GifExportState>,
+    /// Outcome of the last GIF or sound export, shown next to the export
+    /// buttons.
+    export_status: Option<Result<String, String>>,
     current_file: Option<PathBuf>,
     is_dirty: bool,
 }
@@ -633,7 +737,7 @@ impl ShadyApp {
         style.spacing.button_padding = egui::vec2(12.0, 6.0);
         style.spacing.item_spacing = egui::vec2(8.0, 6.0);
 
-        let mut scroll = style.spacing.scroll.clone();
+        let mut scroll = style.spacing.scroll;
         scroll.bar_width = 12.0;
         scroll.handle_min_length = 40.0;
         scroll.floating = false;
@@ -641,14 +745,30 @@ impl ShadyApp {
 
         ctx.set_style(style);
 
+        let live = LiveShader::new(&gl);
+        // Without it each shader falls back to creating its own.
+        let noise_texture = unsafe { create_noise_texture(&gl) }.ok();
+
         let mut this = Self {
             gl,
             snippet: DEFAULT_SNIPPET.to_owned(),
             last_error: None,
-            shader: None,
-            start_time: Instant::now(),
+            diagnostics: Vec::new(),
+            other_mode_errors: Vec::new(),
+            jump_to: None,
+            live,
+            mouse: MouseInput::default(),
+            keyboard: KeyboardInput::default(),
             needs_recompile: true,
+            edited_at: None,
+            recompile_delay_ms: RECOMPILE_DELAY_MS,
+            noise_texture,
+            editor_mode: ShaderMode::Tweet,
+            syntax: glsl_syntax(ShaderMode::Tweet),
+            completer: Completer::new_with_syntax(&glsl_syntax(ShaderMode::Tweet))
+                .with_user_words(),
             gif_export: None,
+            export_status: None,
             current_file: None,
             is_dirty: false,
         };
@@ -657,18 +777,190 @@ impl ShadyApp {
         this
     }
 
-    fn recompile(&mut self) {
-        match ShaderState::new(&self.gl, &self.snippet) {
-            Ok(new_shader) => {
-                self.shader = Some(Arc::new(Mutex::new(new_shader)));
-                self.last_error = None;
+    fn shader_options(&self) -> ShaderOptions {
+        ShaderOptions {
+            base_dir: self
+                .current_file
+                .as_ref()
+                .and_then(|p| p.parent())
+                .map(Path::to_path_buf),
+            noise_texture: self.noise_texture,
+            ..Default::default()
+        }
+    }
+
+    /// Binds a channel of the Image pass by rewriting its pragma, so the
+    /// choice is saved with the shader.
+    fn set_channel(&mut self, channel: usize, config: ChannelConfig) {
+        let updated = set_channel_pragma(&self.snippet, channel, &config);
+        if updated != self.snippet {
+            self.snippet = updated;
+            self.is_dirty = true;
+            self.needs_recompile = true;
+        }
+    }
+
+    /// Contents of the error panel over the preview: the messages with a
+    /// line, as links to it, the full log, the other modes' logs folded
+    /// away, and a way back to the last good snippet.
+    fn error_details(&mut self, ui: &mut egui::Ui, err: &str) {
+        ui.label(
+            egui::RichText::new("⚠ Shader error")
+                .strong()
+                .color(egui::Color32::from_rgb(255, 220, 220))
+                .size(13.0),
+        );
+        ui.add_space(6.0);
+
+        // Click a message to jump to its line
+        for diagnostic in &self.diagnostics {
+            let Some(line) = diagnostic.line else {
+                continue;
+            };
+            let text = egui::RichText::new(diagnostic.to_string())
+                .monospace()
+                .size(12.0)
+                .color(egui::Color32::from_rgb(255, 230, 230));
+            if ui
+                .link(text)
+                .on_hover_text(format!("Go to line {line}"))
+                .clicked()
+            {
+                self.jump_to = Some((line, diagnostic.column));
             }
-            Err(err) => {
-                self.shader = None;
-                self.last_error = Some(err);
+        }
+        if !self.diagnostics.is_empty() {
+            ui.add_space(6.0);
+        }
+
+        ui.label(
+            egui::RichText::new(err)
+                .monospace()
+                .size(12.0)
+                .color(egui::Color32::from_rgb(250, 200, 200))
+                .line_height(Some(16.0)),
+        );
+
+        for (mode, log) in &self.other_mode_errors {
+            ui.add_space(6.0);
+            egui::CollapsingHeader::new(
+                egui::RichText::new(format!("{} mode also failed", mode.label()))
+                .size(12.0)
+                .color(egui::Color32::from_rgb(200, 170, 170)),
+            )
+            .id_salt(("other_mode_error", mode.to_spec()))
+            .show(ui, |ui| {
+                ui.label(
+                    egui::RichText::new(log)
+                        .monospace()
+                        .size(11.0)
+                        .color(egui::Color32::from_rgb(200, 170, 170)),
+                );
+            });
+        }
+
+        let can_revert = self.live.snippet().is_some_and(|good| good != self.snippet);
+        if can_revert {
+            ui.add_space(8.0);
+            if ui
+                .button("↶ Revert to last good")
+                .on_hover_text("Replace the code with the last version that compiled")
+                .clicked()
+            {
+                self.revert_to_last_good();
             }
         }
+    }
+
+    fn revert_to_last_good(&mut self) {
+        if let Some(good) = self.live.snippet() {
+            self.snippet = good.to_owned();
+            self.is_dirty = true;
+            self.needs_recompile = true;
+        }
+    }
+
+    /// Follows the forced mode, or else the one the shader compiled in, with
+    /// the editor's highlighting, completion and docs.
+    fn update_editor_mode(&mut self) {
+        let mode = mode_pragma(&self.snippet)
+            .ok()
+            .flatten()
+            .or_else(|| self.live.shader().map(|shader| shader.lock().mode()))
+            .unwrap_or(ShaderMode::Tweet);
+        if mode != self.editor_mode {
+            self.editor_mode = mode;
+            self.syntax = glsl_syntax(mode);
+            self.completer = Completer::new_with_syntax(&self.syntax).with_user_words();
+        }
+    }
+
+    /// Forces `mode` with a `mode` pragma, or goes back to detecting it.
+    fn set_mode(&mut self, mode: Option<ShaderMode>) {
+        let updated = set_mode_pragma(&self.snippet, mode);
+        if updated != self.snippet {
+            self.snippet = updated;
+            self.is_dirty = true;
+            self.needs_recompile = true;
+        }
+    }
+
+    /// Asks for a texture file and binds it to `channel`, keeping the
+    /// channel's sampler options. Paths inside the shader's directory are
+    /// stored relative to it.
+    fn pick_channel_file(&mut self, channel: usize, config: ChannelConfig) {
+        let Some(path) = FileDialog::new()
+            .add_filter("Channel inputs", &["png", "jpg", "jpeg", "gif", "wav"])
+            .pick_file()
+        else {
+            return;
+        };
+        let path = match self.shader_options().base_dir {
+            Some(dir) => path
+                .strip_prefix(&dir)
+                .map(Path::to_path_buf)
+                .unwrap_or(path),
+            None => path,
+        };
+        self.set_channel(
+            channel,
+            ChannelConfig {
+                source: ChannelSource::from_file(path),
+                ..config
+            },
+        );
+    }
+
+    /// Starts compiling the snippet, dropping any compile still running.
+    /// The current shader keeps drawing until the new one is ready.
+    fn recompile(&mut self) {
         self.needs_recompile = false;
+        self.edited_at = None;
+        let options = self.shader_options();
+        match self.live.compile(&self.gl, &self.snippet, &options) {
+            Ok(()) => self.poll_pending_shader(),
+            Err(err) => self.show_compile_error(err),
+        }
+    }
+
+    /// Swaps in the pending shader once the driver is done with it.
+    fn poll_pending_shader(&mut self) {
+        match self.live.poll(&self.gl) {
+            Some(Ok(())) => {
+                self.last_error = None;
+                self.diagnostics.clear();
+                self.other_mode_errors.clear();
+            }
+            Some(Err(err)) => self.show_compile_error(err),
+            None => {}
+        }
+    }
+
+    /// Shows why the snippet didn't compile, over the last good shader.
+    fn show_compile_error(&mut self, err: ShaderError) {
+        self.last_error = Some(err.message);
+        self.diagnostics = err.diagnostics;
+        self.other_mode_errors = err.other_modes;
     }
 
     fn start_gif_export(&mut self) {
@@ -676,41 +968,37 @@ impl ShadyApp {
             return;
         }
 
-        let shader = match &self.shader {
-            Some(shader) => shader.clone(),
-            None => {
-                self.last_error = Some("No compiled shader to export".to_owned());
-                return;
-            }
+        // Export the shader on screen, even while the editor holds a broken
+        // edit.
+        let Some(snippet) = self.live.snippet().map(str::to_owned) else {
+            self.export_status = Some(Err("No compiled shader to export".to_owned()));
+            return;
         };
 
-        let width = 512u32;
-        let height = 512u32;
-        let fps = 30u32;
-        let seconds = 3u32;
-        let frame_count = fps * seconds;
-
-        let file = match File::create("shady_export.gif") {
-            Ok(file) => file,
-            Err(e) => {
-                self.last_error = Some(format!("Failed to create GIF file: {e}"));
+        // The export gets its own copy of the shader so that buffer passes
+        // start from cleared buffers and the preview is left untouched.
+        let shader = match ShaderState::new(&self.gl, &snippet, &self.shader_options()) {
+            Ok(shader) => shader,
+            Err(err) => {
+                self.export_status = Some(Err(err.to_string()));
                 return;
             }
         };
-        let writer = BufWriter::new(file);
 
-        let mut encoder = match GifEncoder::new(writer, width as u16, height as u16, &[]) {
+        let [width, height] = EXPORT_SIZE;
+        let fps = EXPORT_FPS;
+        let frame_count = (EXPORT_SECONDS * fps as f32).round() as u32;
+
+        let encoder = match create_gif_encoder(Path::new("shady_export.gif"), [width, height]) {
             Ok(encoder) => encoder,
-            Err(e) => {
-                self.last_error = Some(format!("Failed to create GIF encoder: {e}"));
+            Err(err) => {
+                shader.destroy(&self.gl);
+                self.export_status = Some(Err(err));
                 return;
             }
         };
 
-        if let Err(e) = encoder.set_repeat(Repeat::Infinite) {
-            self.last_error = Some(format!("Failed to set GIF repeat: {e}"));
-            return;
-        }
+        self.export_status = None;
 
         self.gif_export = Some(GifExportState {
             encoder,
@@ -723,61 +1011,81 @@ impl ShadyApp {
         });
     }
 
+    /// Renders the Sound pass to `shady_export.wav`.
+    fn export_sound(&mut self) {
+        let Some(shader) = self.live.shader() else {
+            self.export_status = Some(Err("No compiled shader to export".to_owned()));
+            return;
+        };
+
+        let result = shader
+            .lock()
+            .render_sound(&self.gl, SOUND_EXPORT_SECONDS)
+            .and_then(|samples| write_sound_wav(Path::new("shady_export.wav"), &samples));
+        self.export_status = Some(result.map(|()| "Saved shady_export.wav".to_owned()));
+    }
+
     fn step_gif_export(&mut self) {
         let Some(export) = self.gif_export.as_mut() else {
             return;
         };
 
         if export.frame_index >= export.frame_count {
-            self.gif_export = None;
+            self.finish_gif_export();
             return;
         }
 
-        let result: Result<(), String> = (|| {
-            let t = export.frame_index as f32 / export.fps as f32;
-
-            let mut rgba = export
-                .shader
-                .lock()
-                .render_to_image(&self.gl, t, [export.width, export.height])?;
-            let rgba_slice = rgba.as_mut_slice();
-
-            let mut frame = GifFrame::from_rgba_speed(
-                export.width as u16,
-                export.height as u16,
-                rgba_slice,
-                10,
-            );
-            frame.delay = (100 / export.fps) as u16;
-
-            export
-                .encoder
-                .write_frame(&frame)
-                .map_err(|e| format!("Failed to write GIF frame: {e}"))?;
-
-            Ok(())
-        })();
+        let result = write_gif_frame(
+            &mut export.encoder,
+            &mut export.shader,
+            &self.gl,
+            export.frame_index,
+            export.frame_count,
+            export.fps,
+            [export.width, export.height],
+        );
 
         match result {
             Ok(()) => {
                 export.frame_index += 1;
                 if export.frame_index >= export.frame_count {
-                    self.gif_export = None;
+                    self.finish_gif_export();
+                    self.export_status = Some(Ok("Saved shady_export.gif".to_owned()));
                 }
             }
             Err(err) => {
-                self.last_error = Some(err);
-                self.gif_export = None;
+                self.export_status = Some(Err(err));
+                self.finish_gif_export();
             }
         }
     }
+
+    fn finish_gif_export(&mut self) {
+        if let Some(export) = self.gif_export.take() {
+            export.shader.destroy(&self.gl);
+        }
+    }
 }
 
 impl eframe::App for ShadyApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        if let Some(edited_at) = self.edited_at {
+            let delay = Duration::from_millis(self.recompile_delay_ms.into());
+            let waited = edited_at.elapsed();
+            if waited >= delay {
+                self.needs_recompile = true;
+            } else {
+                ctx.request_repaint_after(delay - waited);
+            }
+        }
         if self.needs_recompile {
             self.recompile();
         }
+        self.poll_pending_shader();
+        if self.live.is_compiling() {
+            ctx.request_repaint();
+        }
+        self.update_editor_mode();
 
         let accent = egui::Color32::from_rgb(99, 102, 241);
         let success_color = egui::Color32::from_rgb(34, 197, 94);
@@ -812,8 +1120,29 @@ impl eframe::App for ShadyApp {
                     ui.add_space(12.0);
 
                     // Status indicator dot with tooltip
-                    let (status_color, status_tip) = if self.last_error.is_some() {
-                        (error_color, "Shader has errors")
+                    let error_tip;
+                    let target_error = self
+                        .live
+                        .shader()
+                        .and_then(|shader| shader.lock().target_error().map(str::to_owned));
+                    let (status_color, status_tip) = if self.live.is_compiling()
+                        || self.edited_at.is_some()
+                    {
+                        (accent, "Compiling...")
+                    } else if self.last_error.is_some() {
+                        let first_line = self.diagnostics.iter().find_map(|d| d.line);
+                        let mut tip = match first_line {
+                            Some(line) => format!("Shader has errors, first on line {line}"),
+                            None => "Shader has errors".to_owned(),
+                        };
+                        if self.live.shader().is_some() {
+                            tip.push_str(" (showing the last good one)");
+                        }
+                        error_tip = tip;
+                        (error_color, error_tip.as_str())
+                    } else if let Some(err) = target_error {
+                        error_tip = format!("Buffer passes can't be drawn at this size: {err}");
+                        (error_color, error_tip.as_str())
                     } else if self.gif_export.is_some() {
                         (accent, "Exporting GIF...")
                     } else {
@@ -824,6 +1153,39 @@ impl eframe::App for ShadyApp {
                     ui.painter().circle_filled(rect.center(), 4.0, status_color);
                     resp.on_hover_text(status_tip);
 
+                    // Active mode, and the selector that forces one
+                    let active_mode = self.live.shader().map(|shader| {
+                        let shader = shader.lock();
+                        (shader.mode(), shader.detection())
+                    });
+                    let mode_tip = match active_mode {
+                        Some((_, detection)) => format!("Mode the shader compiled in\n{detection}"),
+                        None => "Mode the shader compiled in".to_owned(),
+                    };
+                    ui.label(
+                        egui::RichText::new(active_mode.map_or("No mode", |(mode, _)| mode.label()))
+                            .size(12.0)
+                            .color(egui::Color32::from_rgb(170, 170, 190)),
+                    )
+                    .on_hover_text(mode_tip);
+
+                    let forced_mode = mode_pragma(&self.snippet).ok().flatten();
+                    let mut selected_mode = forced_mode;
+                    egui::ComboBox::from_id_salt("mode")
+                        .selected_text(format!(
+                            "Mode: {}",
+                            forced_mode.map_or("Auto", ShaderMode::label)
+                        ))
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut selected_mode, None, "Auto");
+                            for mode in ShaderMode::ALL {
+                                ui.selectable_value(&mut selected_mode, Some(mode), mode.label());
+                            }
+                        });
+                    if selected_mode != forced_mode {
+                        self.set_mode(selected_mode);
+                    }
+
                     ui.add_space(16.0);
 
                     // Export button
@@ -837,6 +1199,36 @@ impl eframe::App for ShadyApp {
                         self.start_gif_export();
                     }
 
+                    let has_sound = self
+                        .live
+                        .shader()
+                        .is_some_and(|shader| shader.lock().has_sound());
+                    let sound_btn = egui::Button::new(
+                        egui::RichText::new(" Export Sound").size(12.0),
+                    );
+                    if ui.add_enabled(has_sound, sound_btn).clicked() {
+                        self.export_sound();
+                    }
+
+                    // Kept short for the top bar; errors show in full on hover.
+                    if let Some(status) = &self.export_status {
+                        let (text, color, tip) = match status {
+                            Ok(message) => {
+                                (format!("✔ {message}"), success_color, String::new())
+                            }
+                            Err(err) => {
+                                ("⚠ Export failed".to_owned(), error_color, format!("{err}\n\n"))
+                            }
+                        };
+                        let label = ui.add(
+                            egui::Label::new(egui::RichText::new(text).size(12.0).color(color))
+                                .sense(egui::Sense::click()),
+                        );
+                        if label.on_hover_text(format!("{tip}Click to dismiss")).clicked() {
+                            self.export_status = None;
+                        }
+                    }
+
                     ui.add_space(16.0);
 
                     // File open/save
@@ -907,7 +1299,7 @@ impl eframe::App for ShadyApp {
 
                     // Right side: time display + reset
                     ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
-                        let secs = self.start_time.elapsed().as_secs_f32();
+                        let secs = self.live.clock().elapsed();
 
                         // Reset time button
                         if ui
@@ -917,7 +1309,7 @@ impl eframe::App for ShadyApp {
                             .on_hover_text("Reset time")
                             .clicked()
                         {
-                            self.start_time = Instant::now();
+                            self.live.clock_mut().reset();
                         }
 
                         ui.add_space(4.0);
@@ -976,21 +1368,52 @@ impl eframe::App for ShadyApp {
                             .color(egui::Color32::from_rgb(180, 180, 200)),
                     );
 
+                    // Render order of multipass shaders
+                    if let Some(shader) = self.live.shader() {
+                        let names = shader.lock().pass_names().join(" → ");
+                        if names != IMAGE_PASS {
+                            ui.label(
+                                egui::RichText::new(names)
+                                    .size(10.0)
+                                    .color(egui::Color32::from_rgb(90, 90, 110)),
+                            )
+                            .on_hover_text("Render passes, in order");
+                        }
+                    }
+
                     ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
                         ui.label(
-                            egui::RichText::new("o: vec4 • FC, r, t")
+                            egui::RichText::new(self.editor_mode.hint())
                                 .size(10.0)
                                 .color(egui::Color32::from_rgb(90, 90, 110)),
                         );
                         ui.add_space(8.0);
+                        let inputs = self
+                            .editor_mode
+                            .uniforms()
+                            .iter()
+                            .map(|(_, declaration, doc)| format!("{declaration}: {doc}"))
+                            .collect::<Vec<_>>();
                         ui.label(
                             egui::RichText::new("GLSL")
                                 .size(11.0)
                                 .color(egui::Color32::from_rgb(140, 140, 160)),
                         )
-                        .on_hover_text(
-                            "Output: o (vec4)\nInputs: FC (fragCoord), r (resolution), t (time)",
-                        );
+                        .on_hover_text(if inputs.is_empty() {
+                            "Write a complete fragment shader with its own main() and output."
+                                .to_owned()
+                        } else {
+                            let mode = self.editor_mode.label();
+                            format!("{mode} mode inputs:\n{}", inputs.join("\n"))
+                        });
+                        ui.add_space(8.0);
+                        ui.add(
+                            egui::DragValue::new(&mut self.recompile_delay_ms)
+                                .range(0..=2000)
+                                .speed(10)
+                                .suffix(" ms"),
+                        )
+                        .on_hover_text("How long typing has to pause before the shader recompiles");
                     });
                 });
 
@@ -1015,21 +1438,28 @@ impl eframe::App for ShadyApp {
                             .with_rows(18)
                             .with_fontsize(14.0)
                             .with_theme(ColorTheme::GRUVBOX)
-                            .with_syntax(Syntax::rust())
+                            .with_syntax(self.syntax.clone())
                             .with_numlines(true)
                             .vscroll(true)
-                            .show(ui, &mut self.snippet);
+                            .show_with_completer(ui, &mut self.snippet, &mut self.completer);
 
                         if self.snippet != before {
-                            self.needs_recompile = true;
+                            self.edited_at = Some(Instant::now());
                             self.is_dirty = true;
                         }
 
-                        response
+                        let visible = ui.min_rect();
+                        paint_diagnostics(ui, &response, visible, &self.snippet, &self.diagnostics);
+                        show_glsl_docs(ui, &response, visible, &self.snippet, self.editor_mode);
+                        if let Some((line, column)) = self.jump_to.take() {
+                            jump_to_line(ui, &response, visible, &self.snippet, line, column);
+                        }
+
+                        response.response
                     });
 
                 // Custom focus border around the whole editor card
-                if editor_frame.inner.response.has_focus() {
+                if editor_frame.inner.has_focus() {
                     // Draw just inside the frame so it is never clipped on the right
                     let rect = editor_frame.response.rect.shrink(1.0);
                     ui.painter().rect_stroke(
@@ -1041,6 +1471,107 @@ impl eframe::App for ShadyApp {
                 }
             });
 
+        // Channel slots of the Image pass
+        egui::TopBottomPanel::bottom("channel_bar")
+            .frame(
+                egui::Frame::new()
+                    .fill(egui::Color32::from_rgb(20, 20, 26))
+                    .inner_margin(egui::Margin::symmetric(16, 8))
+                    .stroke(egui::Stroke::new(1.0, border_color)),
+            )
+            .show(ctx, |ui| {
+                let (channels, buffers) = match split_passes(&self.snippet) {
+                    Ok((_, passes)) => {
+                        let channels = passes
+                            .iter()
+                            .find(|p| p.name == IMAGE_PASS)
+                            .map(|p| p.channels.clone())
+                            .unwrap_or_default();
+                        let buffers: Vec<String> = passes
+                            .into_iter()
+                            .filter(|p| p.name != IMAGE_PASS && p.name != SOUND_PASS)
+                            .map(|p| p.name)
+                            .collect();
+                        (channels, buffers)
+                    }
+                    Err(_) => (Default::default(), Vec::new()),
+                };
+
+                ui.horizontal(|ui| {
+                    for (i, channel) in channels.iter().enumerate() {
+                        ui.label(
+                            egui::RichText::new(CHANNEL_NAMES[i])
+                                .monospace()
+                                .size(11.0)
+                                .color(egui::Color32::from_rgb(140, 140, 160)),
+                        );
+                        let label = channel.source.label();
+                        ui.menu_button(egui::RichText::new(label).size(12.0), |ui| {
+                            let with_source = |source| ChannelConfig {
+                                source,
+                                ..channel.clone()
+                            };
+                            if ui.button("Noise").clicked() {
+                                self.set_channel(i, with_source(ChannelSource::Noise));
+                                ui.close();
+                            }
+                            if ui.button("Keyboard").clicked() {
+                                self.set_channel(i, with_source(ChannelSource::Keyboard));
+                                ui.close();
+                            }
+                            for name in &buffers {
+                                if ui.button(name).clicked() {
+                                    self.set_channel(i, with_source(ChannelSource::Buffer(name.clone())));
+                                    ui.close();
+                                }
+                            }
+                            if ui.button("Image, GIF or WAV…").clicked() {
+                                ui.close();
+                                self.pick_channel_file(i, channel.clone());
+                            }
+
+                            ui.separator();
+
+                            // Sampler options of the channel
+                            let sampler = channel.sampler();
+                            let mut updated = channel.clone();
+                            ui.horizontal(|ui| {
+                                ui.label("Filter");
+                                for (filter, name) in [
+                                    (TextureFilter::Nearest, "nearest"),
+                                    (TextureFilter::Linear, "linear"),
+                                    (TextureFilter::Mipmap, "mipmap"),
+                                ] {
+                                    if ui.selectable_label(sampler.filter == filter, name).clicked() {
+                                        updated.filter = Some(filter);
+                                    }
+                                }
+                            });
+                            ui.horizontal(|ui| {
+                                ui.label("Wrap");
+                                for (wrap, name) in
+                                    [(TextureWrap::Clamp, "clamp"), (TextureWrap::Repeat, "repeat")]
+                                {
+                                    if ui.selectable_label(sampler.wrap == wrap, name).clicked() {
+                                        updated.wrap = Some(wrap);
+                                    }
+                                }
+                            });
+                            let mut vflip = sampler.vflip;
+                            if ui.checkbox(&mut vflip, "Flip vertically").changed() {
+                                updated.vflip = Some(vflip);
+                            }
+                            if updated != *channel {
+                                self.set_channel(i, updated);
+                            }
+                        })
+                        .response
+                        .on_hover_text(channel.to_spec());
+                        ui.add_space(12.0);
+                    }
+                });
+            });
+
         // Preview panel (main area)
         egui::CentralPanel::default()
             .frame(
@@ -1066,77 +1597,54 @@ impl eframe::App for ShadyApp {
                             color: egui::Color32::from_rgba_unmultiplied(0, 0, 0, 120),
                         })
                         .show(ui, |ui| {
-                            let (rect, _response) =
-                                ui.allocate_exact_size(size, egui::Sense::hover());
+                            let (rect, response) =
+                                ui.allocate_exact_size(size, egui::Sense::click_and_drag());
 
-                            let time = self.start_time.elapsed().as_secs_f32();
+                            update_input(ui, &response, &mut self.mouse, &mut self.keyboard);
 
-                            let pointer_pos = ctx.input(|i| i.pointer.hover_pos());
-                            let mouse_local = pointer_pos.and_then(|pos| {
-                                if rect.contains(pos) {
-                                    Some(pos - rect.min)
-                                } else {
-                                    None
-                                }
-                            });
+                            let keyboard = self.keyboard.clone();
+                            if let Some(callback) =
+                                self.live.paint_callback(rect, self.mouse, keyboard)
+                            {
+                                ui.painter().add(callback);
+                                self.keyboard.end_frame();
+                            } else {
+                                ui.painter()
+                                    .rect_filled(rect, 8.0, egui::Color32::BLACK);
+                            }
 
-                            if let Some(shader) = &self.shader {
-                                let shader = shader.clone();
-                                let resolution = rect.size();
-                                let rect_min = rect.min;
-
-                                let callback = egui::PaintCallback {
-                                    rect,
-                                    callback: Arc::new(egui_glow::CallbackFn::new(
-                                        move |_info, painter| {
-                                            let gl = painter.gl();
-                                            shader
-                                                .lock()
-                                                .paint(gl, time, rect_min, resolution, mouse_local);
-                                        },
-                                    )),
+                            if let Some(err) = self.last_error.clone() {
+                                // While the last good shader keeps running,
+                                // the errors only cover the bottom of it.
+                                let (panel_rect, fill) = if self.live.shader().is_some() {
+                                    (
+                                        egui::Rect::from_min_max(
+                                            egui::pos2(
+                                                rect.left(),
+                                                rect.bottom() - rect.height() * 0.45,
+                                            ),
+                                            rect.max,
+                                        ),
+                                        egui::Color32::from_rgba_unmultiplied(40, 12, 14, 225),
+                                    )
+                                } else {
+                                    (rect, error_color.linear_multiply(0.22))
                                 };
-                                ui.painter().add(callback);
-                            } else if let Some(err) = &self.last_error {
                                 let mut err_ui = ui.new_child(
                                     egui::UiBuilder::new()
-                                        .max_rect(rect)
+                                        .max_rect(panel_rect.shrink(8.0))
                                         .layout(egui::Layout::top_down(
                                             egui::Align::Center,
                                         )),
                                 );
-                                err_ui.painter().rect_filled(
-                                    err_ui.max_rect(),
-                                    8.0,
-                                    error_color.linear_multiply(0.22),
-                                );
+                                ui.painter().rect_filled(panel_rect, 8.0, fill);
                                 egui::ScrollArea::both()
                                     .auto_shrink([false; 2])
                                     .show(&mut err_ui, |ui| {
                                         ui.vertical_centered(|ui| {
-                                            ui.label(
-                                                egui::RichText::new("⚠ Shader error")
-                                                    .strong()
-                                                    .color(egui::Color32::from_rgb(
-                                                        255, 220, 220,
-                                                    ))
-                                                    .size(13.0),
-                                            );
-                                            ui.add_space(6.0);
-                                            ui.label(
-                                                egui::RichText::new(err)
-                                                    .monospace()
-                                                    .size(12.0)
-                                                    .color(egui::Color32::from_rgb(
-                                                        250, 200, 200,
-                                                    ))
-                                                    .line_height(Some(16.0)),
-                                            );
+                                            self.error_details(ui, &err)
                                         });
                                     });
-                            } else {
-                                ui.painter()
-                                    .rect_filled(rect, 8.0, egui::Color32::BLACK);
                             }
                         });
                 });
@@ -1146,57 +1654,646 @@ impl eframe::App for ShadyApp {
 
         ctx.request_repaint();
     }
+
+    fn on_exit(&mut self, gl: Option<&glow::Context>) {
+        let Some(gl) = gl else {
+            return;
+        };
+        self.live.destroy(gl);
+        if let Some(export) = self.gif_export.take() {
+            export.shader.destroy(gl);
+        }
+        if let Some(texture) = self.noise_texture.take() {
+            unsafe {
+                use glow::HasContext as _;
+                gl.delete_texture(texture);
+            }
+        }
+    }
 }
 
-fn main() -> eframe::Result<()> {
-    let args: Vec<String> = env::args().skip(1).collect();
+const CLI_USAGE: &str = "\
+Usage:
+  shady                             open the editor
+  shady check <file> [options]      compile a shader and print its errors
+  shady check <dir> [options]       compile every .glsl and .frag file under
+                                    <dir> and print a pass/fail table
+  shady render <file> -o <out.png>  render one frame to a PNG
+  shady export <file> -o <out.gif>  render an animation to a GIF, or to
+                                    numbered PNGs when <out> ends in .png
+
+`shady <file>` is short for `shady check <file>`.
+
+Options:
+  --mode tweet|shadertoy|glsl  force a shader mode
+  --format text|json|gcc       how to print errors: as text on stderr, or as
+                               JSON or gcc-style `file:line:col:` lines on
+                               stdout (default text)
+  --channel0..3 <file>         bind an image, GIF or WAV file to a channel
+  --sound <out.wav>            check: also render the Sound pass to a WAV
+  --sound-seconds <n>          check: length of the WAV (default 10)
+  --report <out.xml|out.json>  check <dir>: also write a JUnit XML or JSON
+                               report
+  -o, --output <path>          render, export: file to write
+  --size <W>x<H>               render, export: frame size (default 512x512)
+  --fps <n>                    render, export: frame rate (default 30)
+  --time <seconds>             render: time of the frame (default 0)
+  --duration <seconds>         export: length of the animation (default 3)";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum CliCommand {
+    Check,
+    Render,
+    Export,
+}
 
-    if let Some(path) = args.get(0) {
-        // CLI mode: compile the given file once and print any errors.
-        let source = match fs::read_to_string(path) {
-            Ok(src) => src,
-            Err(e) => {
-                eprintln!("Failed to read file {}: {}", path, e);
-                process::exit(1);
-            }
+impl CliCommand {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Check => "check",
+            Self::Render => "render",
+            Self::Export => "export",
+        }
+    }
+}
+
+/// How the CLI reports the outcome of a shader.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum OutputFormat {
+    Text,
+    Json,
+    Gcc,
+}
+
+impl OutputFormat {
+    fn parse(spec: &str) -> Result<Self, String> {
+        match spec {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            "gcc" => Ok(Self::Gcc),
+            _ => Err(format!("Unknown format `{spec}`, expected text, json or gcc")),
+        }
+    }
+}
+
+/// A parsed command line.
+struct CliArgs {
+    command: CliCommand,
+    path: PathBuf,
+    format: OutputFormat,
+    options: ShaderOptions,
+    output: Option<PathBuf>,
+    sound: Option<(PathBuf, f32)>,
+    report: Option<PathBuf>,
+    size: [u32; 2],
+    fps: u32,
+    time: f32,
+    duration: f32,
+}
+
+/// Parses the command line; `None` opens the editor.
+fn parse_cli_args(args: impl IntoIterator<Item = String>) -> Result<Option<CliArgs>, String> {
+    let mut args = args.into_iter().peekable();
+    let command = match args.peek().map(String::as_str) {
+        None => return Ok(None),
+        Some("check") => CliCommand::Check,
+        Some("render") => CliCommand::Render,
+        Some("export") => CliCommand::Export,
+        // A bare file is checked.
+        Some(_) => CliCommand::Check,
+    };
+    if args.peek().map(String::as_str) == Some(command.name()) {
+        args.next();
+    }
+
+    fn value<T: std::str::FromStr>(
+        args: &mut impl Iterator<Item = String>,
+        flag: &str,
+        expected: &str,
+    ) -> Result<T, String> {
+        args.next()
+            .and_then(|value| value.parse().ok())
+            .ok_or_else(|| format!("Expected {expected} after {flag}"))
+    }
+
+    let mut path = None;
+    let mut cli = CliArgs {
+        command,
+        path: PathBuf::new(),
+        format: OutputFormat::Text,
+        options: ShaderOptions::default(),
+        output: None,
+        sound: None,
+        report: None,
+        size: EXPORT_SIZE,
+        fps: EXPORT_FPS,
+        time: 0.0,
+        duration: EXPORT_SECONDS,
+    };
+    let mut sound_seconds = SOUND_EXPORT_SECONDS;
+
+    while let Some(arg) = args.next() {
+        let applies = match arg.as_str() {
+            "--sound" | "--sound-seconds" | "--report" => command == CliCommand::Check,
+            "-o" | "--output" | "--size" | "--fps" => command != CliCommand::Check,
+            "--time" => command == CliCommand::Render,
+            "--duration" => command == CliCommand::Export,
+            _ => true,
         };
+        if !applies {
+            return Err(format!("{arg} doesn't apply to `{}`", command.name()));
+        }
 
-        let result: Arc<Mutex<Option<String>>> = Arc::new(Mutex::new(None));
-        let result_clone = result.clone();
-        let source_clone = source.clone();
+        // `--channelN <file>` binds a file to the Image pass's iChannelN.
+        let channel = arg
+            .strip_prefix("--channel")
+            .and_then(|n| n.parse::<usize>().ok())
+            .filter(|&n| n < CHANNEL_NAMES.len());
+        if let Some(channel) = channel {
+            let file: String = value(&mut args, &arg, "a file")?;
+            let file = std::path::absolute(&file).unwrap_or_else(|_| PathBuf::from(file));
+            cli.options.channel_overrides[channel] = Some(ChannelConfig {
+                source: ChannelSource::from_file(file),
+                ..Default::default()
+            });
+        } else if arg == "--mode" {
+            let mode: String = value(&mut args, &arg, "a mode")?;
+            cli.options.mode = Some(ShaderMode::parse(&mode)?);
+        } else if arg == "--format" {
+            let format: String = value(&mut args, &arg, "a format")?;
+            cli.format = OutputFormat::parse(&format)?;
+        } else if arg == "--sound" {
+            cli.sound = Some((value(&mut args, &arg, "a file")?, 0.0));
+        } else if arg == "--report" {
+            cli.report = Some(value(&mut args, &arg, "a file")?);
+        } else if arg == "--sound-seconds" {
+            sound_seconds = value(&mut args, &arg, "a number of seconds")?;
+        } else if arg == "-o" || arg == "--output" {
+            cli.output = Some(value(&mut args, &arg, "a file")?);
+        } else if arg == "--size" {
+            let size: String = value(&mut args, &arg, "a size like 512x512")?;
+            cli.size = size
+                .split_once('x')
+                .and_then(|(w, h)| Some([w.parse().ok()?, h.parse().ok()?]))
+                .filter(|size: &[u32; 2]| size.iter().all(|&n| (1..=16384).contains(&n)))
+                .ok_or_else(|| format!("Invalid size `{size}`, expected one like 512x512"))?;
+        } else if arg == "--fps" {
+            cli.fps = value(&mut args, &arg, "a frame rate")?;
+            if cli.fps == 0 || cli.fps > 100 {
+                return Err("The frame rate must be between 1 and 100".to_owned());
+            }
+        } else if arg == "--time" {
+            cli.time = value(&mut args, &arg, "a time in seconds")?;
+        } else if arg == "--duration" {
+            cli.duration = value(&mut args, &arg, "a number of seconds")?;
+        } else if arg.starts_with('-') {
+            return Err(format!("Unknown option {arg}"));
+        } else if path.is_none() {
+            path = Some(PathBuf::from(arg));
+        } else {
+            return Err(format!("Unexpected argument `{arg}`"));
+        }
+    }
 
-        let native_options = eframe::NativeOptions {
-            renderer: eframe::Renderer::Glow,
-            ..Default::default()
+    cli.path = path.ok_or("Missing shader file")?;
+    if let Some((_, seconds)) = &mut cli.sound {
+        *seconds = sound_seconds;
+    }
+    if command != CliCommand::Check && cli.output.is_none() {
+        return Err(format!("`{}` needs an output file (-o)", command.name()));
+    }
+    Ok(Some(cli))
+}
+
+/// Runs a command line and returns the exit code.
+fn run_cli(mut cli: CliArgs) -> i32 {
+    if cli.path.is_dir() {
+        return run_batch(&cli);
+    }
+    if cli.report.is_some() {
+        eprintln!("--report needs a directory to check");
+        return 2;
+    }
+    cli.options.base_dir = cli.path.parent().map(Path::to_path_buf);
+    let result = match fs::read_to_string(&cli.path) {
+        Ok(source) => with_cli_gl(|gl| match cli.command {
+            CliCommand::Check => compile_job(gl, &source, &cli.options, cli.sound.as_ref()),
+            CliCommand::Render => render_job(gl, &source, &cli),
+            CliCommand::Export => export_job(gl, &source, &cli),
+        })
+        .unwrap_or_else(|e| Err(format!("Failed to initialize GL context: {e}").into())),
+        Err(e) => Err(format!("Failed to read file {}: {e}", cli.path.display()).into()),
+    };
+
+    let file = cli.path.display().to_string();
+    match cli.format {
+        OutputFormat::Text => {
+            if let Err(err) = &result {
+                eprintln!("{err}");
+            }
+        }
+        OutputFormat::Json => println!("{}", json_report(&file, &result)),
+        OutputFormat::Gcc => {
+            for line in gcc_report(&file, &result) {
+                println!("{line}");
+            }
+        }
+    }
+    if result.is_ok() { 0 } else { 1 }
+}
+
+/// Outcome of one file of a batch check.
+struct BatchResult {
+    /// Path relative to the checked directory.
+    name: String,
+    result: Result<BuiltShader, ShaderError>,
+    seconds: f64,
+}
+
+/// Checks every shader file under `cli.path` in one GL context, prints a
+/// table of the results and writes the `--report`. Returns the exit code.
+fn run_batch(cli: &CliArgs) -> i32 {
+    if cli.command != CliCommand::Check {
+        eprintln!("`{}` needs a shader file, not a directory", cli.command.name());
+        return 2;
+    }
+    if cli.sound.is_some() {
+        eprintln!("--sound needs a single shader file");
+        return 2;
+    }
+    let files = match shader_files(&cli.path) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("{e}");
+            return 1;
+        }
+    };
+    if files.is_empty() {
+        eprintln!("No .glsl or .frag files in {}", cli.path.display());
+        return 1;
+    }
+
+    let results = with_cli_gl(|gl| {
+        files
+            .iter()
+            .map(|path| {
+                let name = path.strip_prefix(&cli.path).unwrap_or(path);
+                let mut options = cli.options.clone();
+                options.base_dir = path.parent().map(Path::to_path_buf);
+                let started = Instant::now();
+                let result = fs::read_to_string(path)
+                    .map_err(|e| ShaderError::from(format!("Failed to read file: {e}")))
+                    .and_then(|source| compile_job(gl, &source, &options, None));
+                BatchResult {
+                    name: name.display().to_string(),
+                    result,
+                    seconds: started.elapsed().as_secs_f64(),
+                }
+            })
+            .collect::<Vec<_>>()
+    });
+    let results = match results {
+        Ok(results) => results,
+        Err(e) => {
+            eprintln!("Failed to initialize GL context: {e}");
+            return 1;
+        }
+    };
+
+    match cli.format {
+        OutputFormat::Text => print_batch_table(&results),
+        OutputFormat::Json => println!("{}", batch_json(&results)),
+        OutputFormat::Gcc => {
+            for batch in &results {
+                for line in gcc_report(&batch.name, &batch.result) {
+                    println!("{line}");
+                }
+            }
+        }
+    }
+    if let Some(report) = &cli.report {
+        let json = report.extension().is_some_and(|e| e.eq_ignore_ascii_case("json"));
+        let contents = if json { batch_json(&results) } else { batch_junit(&results) };
+        if let Err(e) = fs::write(report, contents + "\n") {
+            eprintln!("Failed to write {}: {e}", report.display());
+            return 1;
+        }
+    }
+    if results.iter().all(|batch| batch.result.is_ok()) { 0 } else { 1 }
+}
+
+/// Shader files (`.glsl` and `.frag`) under `dir`, sorted, skipping hidden
+/// entries and symlinked directories, which could link back up the tree.
+fn shader_files(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let entries =
+        fs::read_dir(dir).map_err(|e| format!("Failed to read directory {}: {e}", dir.display()))?;
+    let mut paths: Vec<PathBuf> = entries.filter_map(|e| e.ok().map(|e| e.path())).collect();
+    paths.sort();
+
+    let mut files = Vec::new();
+    for path in paths {
+        let hidden = path.file_name().and_then(|n| n.to_str()).is_some_and(|n| n.starts_with('.'));
+        if hidden {
+            continue;
+        }
+        if path.is_dir() {
+            if !path.is_symlink() {
+                files.extend(shader_files(&path)?);
+            }
+        } else if path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| e.eq_ignore_ascii_case("glsl") || e.eq_ignore_ascii_case("frag"))
+        {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+/// Prints one line per file with its result, mode and first error, then a
+/// summary.
+fn print_batch_table(results: &[BatchResult]) {
+    let width = results.iter().map(|batch| batch.name.len()).max().unwrap_or(0).max(4);
+    println!("{:<6} {:<10} {:<width$}  ERROR", "RESULT", "MODE", "FILE");
+    for batch in results {
+        let (status, mode, error) = match &batch.result {
+            Ok(built) => ("ok", Some(built.mode), String::new()),
+            Err(err) => ("FAIL", err.mode, first_error(err)),
         };
+        let mode = mode.map_or("-", ShaderMode::to_spec);
+        let line = format!("{status:<6} {mode:<10} {:<width$}  {error}", batch.name);
+        println!("{}", line.trim_end());
+    }
+    let failed = results.iter().filter(|batch| batch.result.is_err()).count();
+    println!();
+    println!("{} passed, {failed} failed", results.len() - failed);
+}
 
-        let run_result = eframe::run_native(
-            "Shady CLI compile",
-            native_options,
-            Box::new(move |cc| {
-                Ok(Box::new(CliCompileApp::new(
-                    cc,
-                    source_clone.clone(),
-                    result_clone.clone(),
-                )))
-            }),
-        );
+/// The first diagnostic of an error, or its first meaningful line.
+fn first_error(err: &ShaderError) -> String {
+    if let Some(diagnostic) = err.diagnostics.first() {
+        return diagnostic.to_string();
+    }
+    err.message
+        .lines()
+        .find(|line| !line.trim().is_empty() && !line.ends_with("failed:"))
+        .unwrap_or_default()
+        .to_owned()
+}
+
+/// A batch as a JSON object with counts and each file's `json_report`.
+fn batch_json(results: &[BatchResult]) -> String {
+    let failed = results.iter().filter(|batch| batch.result.is_err()).count();
+    let files: Vec<String> =
+        results.iter().map(|batch| json_report(&batch.name, &batch.result)).collect();
+    format!(
+        r#"{{"total":{},"passed":{},"failed":{failed},"files":[{}]}}"#,
+        results.len(),
+        results.len() - failed,
+        files.join(",")
+    )
+}
 
-        match run_result {
-            Err(e) => {
-                eprintln!("Failed to initialize GL context: {}", e);
-                process::exit(1);
+/// A batch as a JUnit XML test suite with one test case per file, named
+/// after the mode it compiled in.
+fn batch_junit(results: &[BatchResult]) -> String {
+    let failed = results.iter().filter(|batch| batch.result.is_err()).count();
+    let total_seconds: f64 = results.iter().map(|batch| batch.seconds).sum();
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml += &format!(
+        "<testsuite name=\"shady\" tests=\"{}\" failures=\"{failed}\" time=\"{:.3}\">\n",
+        results.len(),
+        total_seconds
+    );
+    for batch in results {
+        let mode = match &batch.result {
+            Ok(built) => Some(built.mode),
+            Err(err) => err.mode,
+        };
+        xml += &format!(
+            "  <testcase classname=\"shady.{}\" name=\"{}\" time=\"{:.3}\"",
+            mode.map_or("unknown", ShaderMode::to_spec),
+            xml_escape(&batch.name),
+            batch.seconds
+        );
+        match &batch.result {
+            Ok(_) => xml += "/>\n",
+            Err(err) => {
+                xml += &format!(
+                    ">\n    <failure message=\"{}\">{}</failure>\n  </testcase>\n",
+                    xml_escape(&first_error(err)),
+                    xml_escape(&err.to_string())
+                );
             }
-            Ok(()) => {
-                let lock = result.lock();
-                if let Some(err) = &*lock {
-                    eprintln!("{}", err);
-                    process::exit(1);
-                } else {
-                    // Successful compile.
-                    process::exit(0);
+        }
+    }
+    xml += "</testsuite>";
+    xml
+}
+
+/// Escapes `s` for XML text and attribute values.
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Describes the outcome of a shader as a JSON object: whether it built, the
+/// mode it built in (or the one whose errors are reported), every mode tried,
+/// and the diagnostics with snippet-relative lines.
+fn json_report(file: &str, result: &Result<BuiltShader, ShaderError>) -> String {
+    let mode_json = |mode: ShaderMode, log: Option<&str>| match log {
+        Some(log) => format!(
+            r#"{{"mode":{},"success":false,"log":{}}}"#,
+            json_string(mode.to_spec()),
+            json_string(log)
+        ),
+        None => format!(r#"{{"mode":{},"success":true}}"#, json_string(mode.to_spec())),
+    };
+    let (mode, pass, modes, diagnostics, message) = match result {
+        Ok(built) => {
+            let mut modes: Vec<String> = built
+                .failed_modes
+                .iter()
+                .map(|(mode, log)| mode_json(*mode, Some(log)))
+                .collect();
+            modes.push(mode_json(built.mode, None));
+            (Some(built.mode), None, modes, &[][..], None)
+        }
+        Err(err) => {
+            let mut modes = Vec::new();
+            if let Some(mode) = err.mode {
+                // The log alone, without the lines naming the pass and mode.
+                let mut log = err.message.as_str();
+                if let Some(pass) = &err.pass {
+                    log = log.strip_prefix(&format!("{pass} pass:\n")).unwrap_or(log);
                 }
+                let prefix = format!("{} mode failed:\n", mode.label());
+                log = log.strip_prefix(&prefix).unwrap_or(log);
+                modes.push(mode_json(mode, Some(log)));
+            }
+            for (mode, log) in &err.other_modes {
+                modes.push(mode_json(*mode, Some(log)));
             }
+            let pass = err.pass.as_deref();
+            (err.mode, pass, modes, &err.diagnostics[..], Some(err.to_string()))
+        }
+    };
+    let number = |n: Option<usize>| n.map_or("null".to_owned(), |n| n.to_string());
+    let diagnostics: Vec<String> = diagnostics
+        .iter()
+        .map(|d| {
+            format!(
+                r#"{{"line":{},"column":{},"severity":{},"message":{}}}"#,
+                number(d.line),
+                number(d.column),
+                json_string(d.severity.name()),
+                json_string(&d.message)
+            )
+        })
+        .collect();
+    format!(
+        concat!(
+            r#"{{"file":{},"success":{},"mode":{},"pass":{},"modes":[{}],"diagnostics":[{}],"#,
+            r#""message":{}}}"#
+        ),
+        json_string(file),
+        result.is_ok(),
+        mode.map_or("null".to_owned(), |mode| json_string(mode.to_spec())),
+        pass.map_or("null".to_owned(), json_string),
+        modes.join(","),
+        diagnostics.join(","),
+        message.map_or("null".to_owned(), |message| json_string(&message))
+    )
+}
+
+/// Quotes `s` as a JSON string.
+fn json_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c < ' ' => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Describes a failed shader as `file:line:col: error: message` lines, the
+/// way gcc does, so editors and CI can pick them up. A success prints nothing.
+fn gcc_report(file: &str, result: &Result<BuiltShader, ShaderError>) -> Vec<String> {
+    let Err(err) = result else {
+        return Vec::new();
+    };
+    let mut lines: Vec<String> = err
+        .diagnostics
+        .iter()
+        .map(|d| match d.line {
+            Some(_) => format!("{file}:{d}"),
+            None => format!("{file}: {}: {}", d.severity.name(), d.message),
+        })
+        .collect();
+    if lines.is_empty() {
+        let message: Vec<&str> = err.message.lines().filter(|l| !l.trim().is_empty()).collect();
+        lines.push(format!("{file}: error: {}", message.join(" ")));
+    }
+    if let Some(mode) = err.mode {
+        lines.push(format!("{file}: note: errors are from {} mode", mode.label()));
+    }
+    for (mode, _) in &err.other_modes {
+        lines.push(format!("{file}: note: {} mode also failed", mode.label()));
+    }
+    lines
+}
+
+/// Renders the frame at `--time` to a PNG.
+fn render_job(
+    gl: &glow::Context,
+    snippet: &str,
+    cli: &CliArgs,
+) -> Result<BuiltShader, ShaderError> {
+    let output = cli.output.as_deref().unwrap_or(Path::new("shady_render.png"));
+    let mut shader = ShaderState::new(gl, snippet, &cli.options)?;
+    let result = shader.render_frame(gl, cli.time, cli.fps, cli.size)
+        .and_then(|pixels| write_png(output, &pixels, cli.size));
+    let built = BuiltShader::of(&shader);
+    shader.destroy(gl);
+    result.map(|()| built).map_err(ShaderError::from)
+}
+
+/// Renders `--duration` seconds at `--fps` to a GIF, or to numbered PNGs.
+fn export_job(
+    gl: &glow::Context,
+    snippet: &str,
+    cli: &CliArgs,
+) -> Result<BuiltShader, ShaderError> {
+    let output = cli.output.as_deref().unwrap_or(Path::new("shady_export.gif"));
+    let extension = output
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase);
+    let gif = match extension.as_deref() {
+        Some("gif") => true,
+        Some("png") => false,
+        _ => return Err("Export writes a .gif file or .png frames".to_owned().into()),
+    };
+    if gif && cli.size.iter().any(|&n| n > u32::from(u16::MAX)) {
+        return Err("GIF frames can't be larger than 65535 pixels".to_owned().into());
+    }
+
+    let mut shader = ShaderState::new(gl, snippet, &cli.options)?;
+    let result: Result<(), String> = (|| {
+        let frame_count = ((cli.duration * cli.fps as f32).round() as u32).max(1);
+        if gif {
+            let mut encoder = create_gif_encoder(output, cli.size)?;
+            for index in 0..frame_count {
+                write_gif_frame(
+                    &mut encoder,
+                    &mut shader,
+                    gl,
+                    index,
+                    frame_count,
+                    cli.fps,
+                    cli.size,
+                )?;
+            }
+        } else {
+            let stem = output.file_stem().and_then(|s| s.to_str()).unwrap_or("frame");
+            for index in 0..frame_count {
+                let clock = FrameClock::fixed(index, cli.fps);
+                let pixels = shader.render_to_image(gl, &clock, cli.size)?;
+                let path = output.with_file_name(format!("{stem}_{index:04}.png"));
+                write_png(&path, &pixels, cli.size)?;
+            }
+        }
+        Ok(())
+    })();
+    let built = BuiltShader::of(&shader);
+    shader.destroy(gl);
+    result.map(|()| built).map_err(ShaderError::from)
+}
+
+fn main() -> eframe::Result<()> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{CLI_USAGE}");
+        return Ok(());
+    }
+    match parse_cli_args(args) {
+        Ok(Some(cli)) => process::exit(run_cli(cli)),
+        Ok(None) => {}
+        Err(err) => {
+            eprintln!("{err}\n\n{CLI_USAGE}");
+            process::exit(2);
         }
     }
 
@@ -1212,3 +2309,116 @@ fn main() -> eframe::Result<()> {
     )
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compile_error() -> ShaderError {
+        ShaderError {
+            message: "Shadertoy mode failed:\n3:10: error: `foo' undeclared".to_owned(),
+            pass: None,
+            mode: Some(ShaderMode::Shadertoy),
+            diagnostics: vec![Diagnostic {
+                line: Some(3),
+                column: Some(10),
+                severity: Severity::Error,
+                message: "`foo' undeclared".to_owned(),
+            }],
+            other_modes: vec![(ShaderMode::Tweet, "1: error: syntax error".to_owned())],
+        }
+    }
+
+    #[test]
+    fn json_report_of_a_single_pass() {
+        let json = json_report("a.glsl", &Err(compile_error()));
+        assert!(json.contains(r#""mode":"shadertoy","pass":null,"#), "{json}");
+        assert!(
+            json.contains(r#""log":"3:10: error: `foo' undeclared"}"#),
+            "{json}"
+        );
+    }
+
+    #[test]
+    fn json_report_of_a_multipass_error() {
+        let json = json_report("a.glsl", &Err(compile_error().in_pass("Buffer A")));
+        assert!(json.contains(r#""pass":"Buffer A""#), "{json}");
+        // The mode's log is the driver's alone, the pass is its own field.
+        assert!(
+            json.contains(
+                r#"{"mode":"shadertoy","success":false,"log":"3:10: error: `foo' undeclared"}"#
+            ),
+            "{json}"
+        );
+        assert!(json.contains(r#""line":3,"column":10"#), "{json}");
+        assert!(
+            json.contains(r#""message":"Buffer A pass:\nShadertoy mode failed:\n3:10"#),
+            "{json}"
+        );
+    }
+
+    #[test]
+    fn json_report_of_a_success() {
+        let built = BuiltShader {
+            mode: ShaderMode::Tweet,
+            failed_modes: Vec::new(),
+        };
+        let json = json_report("a.glsl", &Ok(built));
+        assert_eq!(
+            json,
+            concat!(
+                r#"{"file":"a.glsl","success":true,"mode":"tweet","pass":null,"#,
+                r#""modes":[{"mode":"tweet","success":true}],"diagnostics":[],"message":null}"#
+            )
+        );
+    }
+
+    #[test]
+    fn json_report_of_a_fallback_mode() {
+        let built = BuiltShader {
+            mode: ShaderMode::Full,
+            failed_modes: vec![
+                (ShaderMode::Tweet, "1: error: syntax error".to_owned()),
+                (ShaderMode::Shadertoy, "2: error: `main' redefined".to_owned()),
+            ],
+        };
+        let json = json_report("a.glsl", &Ok(built));
+        assert!(
+            json.contains(concat!(
+                r#""modes":[{"mode":"tweet","success":false,"log":"1: error: syntax error"},"#,
+                r#"{"mode":"shadertoy","success":false,"log":"2: error: `main' redefined"},"#,
+                r#"{"mode":"glsl","success":true}]"#
+            )),
+            "{json}"
+        );
+        assert!(json.contains(r#""success":true,"mode":"glsl","#), "{json}");
+    }
+
+    #[test]
+    fn gif_delays_stay_playable_and_exact() {
+        for fps in [30, 50, 60, 100] {
+            let frame_count = 3 * fps;
+            let delays: Vec<u16> = (0..frame_count)
+                .filter_map(|index| gif_frame_delay(index, frame_count, fps))
+                .collect();
+            assert!(delays.iter().all(|&delay| delay >= 2), "{fps} fps: {delays:?}");
+            assert_eq!(delays.iter().map(|&d| u32::from(d)).sum::<u32>(), 300, "{fps} fps");
+        }
+        // An odd total still ends on a playable frame.
+        let delays: Vec<_> = (0..5).filter_map(|index| gif_frame_delay(index, 5, 100)).collect();
+        assert_eq!(delays, [2, 3]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn shader_files_skip_symlinked_directories() {
+        let dir = env::temp_dir().join(format!("shady_files_{}", process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("sub/a.glsl"), "o = vec4(1);").unwrap();
+        // A link back to the top would recurse forever if followed.
+        std::os::unix::fs::symlink(&dir, dir.join("sub/loop")).unwrap();
+        let files = shader_files(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(files.unwrap(), [dir.join("sub/a.glsl")]);
+    }
+}
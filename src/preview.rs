@@ -0,0 +1,460 @@
+//! A live shader preview for egui: [`ShaderPreview`] is a widget that
+//! compiles its snippet, keeps drawing the last shader that compiled while an
+//! edit is broken, and feeds the shader the mouse and keyboard. The pieces it
+//! is built from are public too, for apps that manage shaders themselves.
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use eframe::egui::mutex::Mutex;
+use eframe::{egui, egui_glow, glow};
+
+use crate::{
+    key_code, local_date, FrameClock, KeyboardInput, MouseInput, PendingShader, ShaderBuilder,
+    ShaderError, ShaderMode, ShaderOptions, ShaderState, Uniform,
+};
+
+/// Wall clock of a live preview, counting frames for `iFrame` and smoothing
+/// the frame rate for `iFrameRate`.
+pub struct PreviewClock {
+    start_time: Instant,
+    /// `iFrame` of the next frame.
+    frame: i32,
+    /// `iTime` of the previous frame, for `iTimeDelta`.
+    last_frame_time: f32,
+    frame_rate: f32,
+}
+
+impl Default for PreviewClock {
+    fn default() -> Self {
+        Self {
+            start_time: Instant::now(),
+            frame: 0,
+            last_frame_time: 0.0,
+            frame_rate: 0.0,
+        }
+    }
+}
+
+impl PreviewClock {
+    /// Seconds since the clock started.
+    pub fn elapsed(&self) -> f32 {
+        self.start_time.elapsed().as_secs_f32()
+    }
+
+    /// Starts over from zero seconds and frame 0.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Counts frames from 0 again, for a new shader whose buffers start
+    /// empty.
+    pub fn restart_frames(&mut self) {
+        self.frame = 0;
+    }
+
+    /// Advances the clock by one frame.
+    pub fn next_frame(&mut self) -> FrameClock {
+        let time = self.elapsed();
+        let time_delta = if self.frame == 0 {
+            0.0
+        } else {
+            (time - self.last_frame_time).max(0.0)
+        };
+        if time_delta > 0.0 {
+            let rate = 1.0 / time_delta;
+            self.frame_rate = if self.frame_rate > 0.0 {
+                self.frame_rate * 0.9 + rate * 0.1
+            } else {
+                rate
+            };
+        }
+
+        let clock = FrameClock {
+            time,
+            time_delta,
+            frame: self.frame,
+            frame_rate: self.frame_rate,
+            date: local_date(),
+        };
+        self.frame += 1;
+        self.last_frame_time = time;
+        clock
+    }
+}
+
+/// Updates the shader's mouse and keyboard from the preview's `response`.
+/// Mouse positions are in physical pixels, like `fragCoord`. Clicking the
+/// preview gives it keyboard focus, keeping arrows and tab for the shader;
+/// without focus every key is released.
+pub fn update_input(
+    ui: &egui::Ui,
+    response: &egui::Response,
+    mouse: &mut MouseInput,
+    keyboard: &mut KeyboardInput,
+) {
+    let rect = response.rect;
+    let pixels_per_point = ui.ctx().pixels_per_point();
+    // Shader pixels have their origin at the bottom-left.
+    let to_shader = |pos: egui::Pos2| {
+        let local = (pos - rect.min).clamp(egui::Vec2::ZERO, rect.size());
+        [local.x * pixels_per_point, (rect.height() - local.y) * pixels_per_point]
+    };
+    let pointer_pos = ui.input(|i| i.pointer.interact_pos());
+    mouse.update(response.is_pointer_button_down_on(), pointer_pos.map(to_shader));
+    if let Some(pos) = response.hover_pos() {
+        mouse.set_hover(to_shader(pos));
+    }
+
+    if response.clicked() || response.drag_started() {
+        response.request_focus();
+    }
+    if !response.has_focus() {
+        keyboard.release_all();
+        return;
+    }
+    ui.memory_mut(|m| {
+        m.set_focus_lock_filter(
+            response.id,
+            egui::EventFilter {
+                tab: true,
+                horizontal_arrows: true,
+                vertical_arrows: true,
+                escape: false,
+            },
+        )
+    });
+    ui.input(|i| {
+        for event in &i.events {
+            if let egui::Event::Key { key, pressed, .. } = event {
+                if let Some(code) = key_code(*key) {
+                    keyboard.set_key(code, *pressed);
+                }
+            }
+        }
+        keyboard.set_key(16, i.modifiers.shift);
+        keyboard.set_key(17, i.modifiers.ctrl);
+        keyboard.set_key(18, i.modifiers.alt);
+    });
+}
+
+/// Paint callback that draws one frame of `shader` into `rect`.
+pub fn paint_callback(
+    shader: Arc<Mutex<ShaderState>>,
+    rect: egui::Rect,
+    clock: FrameClock,
+    mouse: MouseInput,
+    keyboard: KeyboardInput,
+) -> egui::PaintCallback {
+    egui::PaintCallback {
+        rect,
+        callback: Arc::new(egui_glow::CallbackFn::new(move |info, painter| {
+            // `gl_FragCoord` is in framebuffer pixels from the bottom-left,
+            // so the rect is too.
+            let viewport = info.viewport_in_pixels();
+            let rect_min = egui::pos2(viewport.left_px as f32, viewport.from_bottom_px as f32);
+            let resolution = egui::vec2(viewport.width_px as f32, viewport.height_px as f32);
+            let gl = painter.gl();
+            shader.lock().paint(gl, &clock, rect_min, resolution, &mouse, &keyboard);
+        })),
+    }
+}
+
+/// The shader of a live preview: the last one that compiled, which keeps
+/// drawing while a newer snippet compiles or fails to, and the clock it runs
+/// on. Both `ShaderPreview` and the Shady app are built on it.
+pub struct LiveShader {
+    /// Last shader that compiled, with its snippet.
+    shader: Option<(Arc<Mutex<ShaderState>>, String)>,
+    /// Shader still compiling, with its snippet.
+    pending_shader: Option<(PendingShader, String)>,
+    /// Whether the driver has `GL_KHR_parallel_shader_compile`.
+    parallel_compile: bool,
+    clock: PreviewClock,
+}
+
+impl LiveShader {
+    pub fn new(gl: &glow::Context) -> Self {
+        Self {
+            shader: None,
+            pending_shader: None,
+            parallel_compile: crate::enable_parallel_compile(gl),
+            clock: PreviewClock::default(),
+        }
+    }
+
+    /// Starts compiling `snippet`, dropping any compile still running. The
+    /// current shader keeps drawing until `poll` swaps in the new one.
+    pub fn compile(
+        &mut self,
+        gl: &glow::Context,
+        snippet: &str,
+        options: &ShaderOptions,
+    ) -> Result<(), ShaderError> {
+        if let Some((pending, _)) = self.pending_shader.take() {
+            pending.discard(gl);
+        }
+        let pending = ShaderState::start(gl, snippet, options, self.parallel_compile)?;
+        self.pending_shader = Some((pending, snippet.to_owned()));
+        Ok(())
+    }
+
+    /// Swaps in the compiling shader once the driver is done with it, and
+    /// restarts the frame count for its empty buffers. Returns how a compile
+    /// that finished went, or `None` while none did.
+    pub fn poll(&mut self, gl: &glow::Context) -> Option<Result<(), ShaderError>> {
+        if !self
+            .pending_shader
+            .as_ref()
+            .is_some_and(|(pending, _)| pending.is_ready(gl))
+        {
+            return None;
+        }
+        let (pending, snippet) = self.pending_shader.take()?;
+        let shader = match pending.finish(gl) {
+            Ok(shader) => shader,
+            Err(err) => return Some(Err(err)),
+        };
+        // The preview drew the old shader last frame, so it's free to go.
+        if let Some((old, _)) = self.shader.replace((Arc::new(Mutex::new(shader)), snippet)) {
+            old.lock().destroy(gl);
+        }
+        self.clock.restart_frames();
+        Some(Ok(()))
+    }
+
+    /// Shader on screen, the last one that compiled.
+    pub fn shader(&self) -> Option<&Arc<Mutex<ShaderState>>> {
+        self.shader.as_ref().map(|(shader, _)| shader)
+    }
+
+    /// Snippet of the shader on screen.
+    pub fn snippet(&self) -> Option<&str> {
+        self.shader.as_ref().map(|(_, snippet)| snippet.as_str())
+    }
+
+    /// Whether a newer snippet is still compiling.
+    pub fn is_compiling(&self) -> bool {
+        self.pending_shader.is_some()
+    }
+
+    pub fn clock(&self) -> &PreviewClock {
+        &self.clock
+    }
+
+    pub fn clock_mut(&mut self) -> &mut PreviewClock {
+        &mut self.clock
+    }
+
+    /// Paint callback drawing the next frame into `rect`, once a shader
+    /// compiled.
+    pub fn paint_callback(
+        &mut self,
+        rect: egui::Rect,
+        mouse: MouseInput,
+        keyboard: KeyboardInput,
+    ) -> Option<egui::PaintCallback> {
+        let (shader, _) = self.shader.as_ref()?;
+        let clock = self.clock.next_frame();
+        Some(paint_callback(shader.clone(), rect, clock, mouse, keyboard))
+    }
+
+    /// Deletes the GL objects of both shaders. Call it with the context
+    /// current, e.g. from `eframe::App::on_exit`; nothing is freed on drop.
+    pub fn destroy(&mut self, gl: &glow::Context) {
+        if let Some((shader, _)) = self.shader.take() {
+            shader.lock().destroy(gl);
+        }
+        if let Some((pending, _)) = self.pending_shader.take() {
+            pending.discard(gl);
+        }
+    }
+}
+
+/// A live shader preview widget. Keep it in the app's state and add it with
+/// `ui.add(&mut preview)` every frame:
+///
+/// ```no_run
+/// # fn app(cc: &eframe::CreationContext<'_>, ui: &mut eframe::egui::Ui) {
+/// use shady::preview::ShaderPreview;
+///
+/// let gl = cc.gl.clone().expect("needs the glow backend");
+/// let mut preview = ShaderPreview::new(gl, "o = vec4(FC / r, sin(t), 1);")
+///     .size(eframe::egui::vec2(128.0, 128.0));
+/// ui.add(&mut preview);
+/// # }
+/// ```
+///
+/// A changed snippet compiles in the background when the driver supports it,
+/// while the previous shader keeps drawing. Compile errors are shown over the
+/// bottom of the preview, or over all of it when nothing compiled yet. Like
+/// `ShaderState`, it frees nothing when dropped: call `destroy` from
+/// `eframe::App::on_exit`.
+pub struct ShaderPreview {
+    gl: Arc<glow::Context>,
+    builder: ShaderBuilder,
+    size: egui::Vec2,
+    needs_compile: bool,
+    live: LiveShader,
+    /// Error of the last compile, if it failed.
+    error: Option<ShaderError>,
+    mouse: MouseInput,
+    keyboard: KeyboardInput,
+}
+
+impl ShaderPreview {
+    pub fn new(gl: Arc<glow::Context>, snippet: impl Into<String>) -> Self {
+        Self::from_builder(gl, ShaderBuilder::new(snippet))
+    }
+
+    /// A preview of the shader `builder` sets up, with its mode, channels
+    /// and uniforms.
+    pub fn from_builder(gl: Arc<glow::Context>, builder: ShaderBuilder) -> Self {
+        let live = LiveShader::new(&gl);
+        Self {
+            gl,
+            builder,
+            size: egui::vec2(256.0, 256.0),
+            needs_compile: true,
+            live,
+            error: None,
+            mouse: MouseInput::default(),
+            keyboard: KeyboardInput::default(),
+        }
+    }
+
+    /// Size of the preview in points, 256×256 by default.
+    pub fn size(mut self, size: egui::Vec2) -> Self {
+        self.size = size;
+        self
+    }
+
+    pub fn snippet(&self) -> &str {
+        &self.builder.snippet
+    }
+
+    /// Replaces the snippet, compiling it when the preview is next shown.
+    pub fn set_snippet(&mut self, snippet: impl Into<String>) {
+        let snippet = snippet.into();
+        if snippet != self.builder.snippet {
+            self.builder.snippet = snippet;
+            self.needs_compile = true;
+        }
+    }
+
+    /// Changes the value of an extra uniform. A uniform the builder didn't
+    /// declare yet is added and the shader recompiled.
+    pub fn set_uniform(&mut self, name: &str, value: Uniform) {
+        let uniforms = &mut self.builder.options.uniforms;
+        match uniforms.iter_mut().find(|(n, _)| n == name) {
+            Some((_, current)) => *current = value,
+            None => {
+                uniforms.push((name.to_owned(), value));
+                self.needs_compile = true;
+            }
+        }
+        if let Some(shader) = self.live.shader() {
+            shader.lock().set_uniform(name, value);
+        }
+    }
+
+    /// Error of the last compile, while the snippet doesn't compile.
+    pub fn error(&self) -> Option<&ShaderError> {
+        self.error.as_ref()
+    }
+
+    /// Mode of the shader on screen.
+    pub fn mode(&self) -> Option<ShaderMode> {
+        self.live.shader().map(|shader| shader.lock().mode())
+    }
+
+    /// Whether a newer snippet is still compiling.
+    pub fn is_compiling(&self) -> bool {
+        self.needs_compile || self.live.is_compiling()
+    }
+
+    /// Restarts the shader's time and frame count.
+    pub fn restart(&mut self) {
+        self.live.clock_mut().reset();
+    }
+
+    /// Deletes the preview's GL objects. Call it with `gl` current, e.g.
+    /// from `eframe::App::on_exit`; the preview shows nothing afterwards.
+    pub fn destroy(&mut self, gl: &glow::Context) {
+        self.live.destroy(gl);
+    }
+
+    /// Starts compiling a changed snippet and swaps in the shader once the
+    /// driver is done with it.
+    fn update_shader(&mut self) {
+        if self.needs_compile {
+            self.needs_compile = false;
+            let started = self.live.compile(&self.gl, &self.builder.snippet, &self.builder.options);
+            if let Err(err) = started {
+                self.error = Some(err);
+            }
+        }
+        match self.live.poll(&self.gl) {
+            Some(Ok(())) => self.error = None,
+            Some(Err(err)) => self.error = Some(err),
+            None => {}
+        }
+    }
+
+    /// Shows the first error over `rect`, with the whole log on hover.
+    fn error_overlay(&self, ui: &mut egui::Ui, rect: egui::Rect, err: &ShaderError) {
+        let first = err
+            .diagnostics
+            .first()
+            .map(ToString::to_string)
+            .unwrap_or_else(|| err.message.lines().next().unwrap_or_default().to_owned());
+        let (panel_rect, fill) = if self.live.shader().is_some() {
+            let height = (rect.height() * 0.3).max(20.0).min(rect.height());
+            (
+                egui::Rect::from_min_max(egui::pos2(rect.left(), rect.bottom() - height), rect.max),
+                egui::Color32::from_rgba_unmultiplied(40, 12, 14, 225),
+            )
+        } else {
+            (rect, egui::Color32::from_rgb(40, 12, 14))
+        };
+        ui.painter().rect_filled(panel_rect, 0.0, fill);
+        let text = egui::RichText::new(format!("⚠ {first}"))
+            .monospace()
+            .size(11.0)
+            .color(egui::Color32::from_rgb(255, 220, 220));
+        let mut err_ui = ui.new_child(
+            egui::UiBuilder::new()
+                .max_rect(panel_rect.shrink(4.0))
+                .layout(egui::Layout::top_down(egui::Align::Min)),
+        );
+        err_ui
+            .add(egui::Label::new(text).selectable(false))
+            .on_hover_text(egui::RichText::new(&err.message).monospace());
+    }
+}
+
+impl egui::Widget for &mut ShaderPreview {
+    fn ui(self, ui: &mut egui::Ui) -> egui::Response {
+        self.update_shader();
+
+        let (rect, response) = ui.allocate_exact_size(self.size, egui::Sense::click_and_drag());
+        update_input(ui, &response, &mut self.mouse, &mut self.keyboard);
+
+        if self.live.shader().is_none() {
+            ui.painter().rect_filled(rect, 0.0, egui::Color32::BLACK);
+        } else if ui.is_rect_visible(rect) {
+            let keyboard = self.keyboard.clone();
+            if let Some(callback) = self.live.paint_callback(rect, self.mouse, keyboard) {
+                ui.painter().add(callback);
+            }
+            self.keyboard.end_frame();
+        }
+        if self.live.shader().is_some() || self.is_compiling() {
+            ui.ctx().request_repaint();
+        }
+        if let Some(err) = &self.error {
+            self.error_overlay(ui, rect, err);
+        }
+        response
+    }
+}
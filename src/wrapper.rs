@@ -0,0 +1,610 @@
+//! Turns snippets into complete shaders, one per mode they may be in, without
+//! touching GL: mode detection, the wrapper templates, the `rgb(` macro
+//! renaming and the maps from compiled lines back to snippet lines.
+
+use std::fmt;
+
+use crate::ShaderMode;
+
+/// GLSL dialect the wrappers are written in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GlslTarget {
+    pub version: &'static str,
+    /// Default precision statement, empty where there is none.
+    pub precision: &'static str,
+}
+
+impl GlslTarget {
+    pub const DESKTOP: Self = Self {
+        version: "#version 330 core",
+        precision: "",
+    };
+    pub const WEB: Self = Self {
+        version: "#version 300 es",
+        precision: "precision mediump float;",
+    };
+
+    /// The dialect of the platform Shady is built for.
+    pub fn current() -> Self {
+        if cfg!(target_arch = "wasm32") {
+            Self::WEB
+        } else {
+            Self::DESKTOP
+        }
+    }
+}
+
+/// Source text along with the snippet line (1-based) each of its lines came
+/// from, so compiler messages can be mapped back to the editor.
+#[derive(Clone, Debug, Default)]
+pub struct MappedSource {
+    pub text: String,
+    pub lines: Vec<usize>,
+}
+
+impl MappedSource {
+    pub fn push_line(&mut self, line: &str, number: usize) {
+        self.text.push_str(line);
+        self.text.push('\n');
+        self.lines.push(number);
+    }
+
+    pub fn append(&mut self, other: &MappedSource) {
+        self.text.push_str(&other.text);
+        self.lines.extend_from_slice(&other.lines);
+    }
+}
+
+/// Why a pass's modes are tried in the order they are.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Detection {
+    /// Set by a `mode` pragma or `--mode`; the only mode tried.
+    Forced(ShaderMode),
+    /// The code uses this Shadertoy identifier.
+    Shadertoy(&'static str),
+    /// The code has this mark of a complete shader.
+    Full(&'static str),
+    /// Nothing points to another mode, so the code is taken for a tweet.
+    Tweet,
+}
+
+impl Detection {
+    /// Modes to try, the likeliest first.
+    pub fn order(self) -> &'static [ShaderMode] {
+        match self {
+            Self::Forced(ShaderMode::Tweet) => &[ShaderMode::Tweet],
+            Self::Forced(ShaderMode::Shadertoy) => &[ShaderMode::Shadertoy],
+            Self::Forced(ShaderMode::Full) => &[ShaderMode::Full],
+            Self::Shadertoy(_) => &[ShaderMode::Shadertoy, ShaderMode::Full, ShaderMode::Tweet],
+            Self::Full(_) => &[ShaderMode::Full, ShaderMode::Shadertoy, ShaderMode::Tweet],
+            Self::Tweet => &[ShaderMode::Tweet, ShaderMode::Shadertoy, ShaderMode::Full],
+        }
+    }
+}
+
+impl fmt::Display for Detection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Forced(mode) => write!(f, "{} mode is forced", mode.label()),
+            Self::Shadertoy(word) => write!(f, "Looks like Shadertoy code: uses `{word}`"),
+            Self::Full(word) => write!(f, "Looks like a complete shader: has `{word}`"),
+            Self::Tweet => f.write_str("Looks like a tweet: no sign of another mode"),
+        }
+    }
+}
+
+/// Identifiers that make code Shadertoy code.
+const SHADERTOY_MARKS: [&str; 3] = ["mainImage", "iTime", "iResolution"];
+/// Text that makes code a complete fragment shader.
+const FULL_MARKS: [&str; 4] = ["void main", "#version", "gl_FragColor", "out vec4"];
+
+/// Guesses the mode of a pass's code, unless `forced`. Comments don't count,
+/// so a credit line mentioning `mainImage` doesn't make a tweet Shadertoy
+/// code.
+pub fn detect_mode(code: &str, forced: Option<ShaderMode>) -> Detection {
+    if let Some(mode) = forced {
+        return Detection::Forced(mode);
+    }
+    let code = strip_comments(code);
+    if let Some(word) = SHADERTOY_MARKS.into_iter().find(|word| code.contains(word)) {
+        return Detection::Shadertoy(word);
+    }
+    if let Some(mark) = FULL_MARKS.into_iter().find(|mark| code.contains(mark)) {
+        return Detection::Full(mark);
+    }
+    Detection::Tweet
+}
+
+/// Replaces the comments of GLSL code by spaces, keeping line breaks.
+pub fn strip_comments(code: &str) -> String {
+    let mut out = String::with_capacity(code.len());
+    let mut chars = code.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('/', Some('/')) => {
+                while chars.next_if(|&c| c != '\n').is_some() {}
+            }
+            ('/', Some('*')) => {
+                chars.next();
+                let mut previous = ' ';
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        out.push('\n');
+                    }
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+                out.push(' ');
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Whether the code declares its own `#version`, on a line of its own.
+fn has_version_line(code: &str) -> bool {
+    strip_comments(code).lines().any(|line| {
+        line.trim_start()
+            .strip_prefix('#')
+            .is_some_and(|rest| rest.trim_start().starts_with("version"))
+    })
+}
+
+/// Function-like macros that some drivers confuse with `.rgb` swizzles, so
+/// Shadertoy code gets them renamed.
+const RENAMED_MACROS: [&str; 2] = ["rgb", "grgb"];
+
+/// Renames the `rgb(` and `grgb(` macros the code defines, and every call of
+/// them, by appending an `x`. Swizzles and other identifiers are left alone,
+/// and no line is added or removed.
+pub fn rename_color_macros(code: &str) -> String {
+    let defines = |name: &str| {
+        code.lines().any(|line| {
+            line.trim_start()
+                .strip_prefix('#')
+                .and_then(|rest| rest.trim_start().strip_prefix("define"))
+                .filter(|rest| rest.starts_with([' ', '\t']))
+                .and_then(|rest| rest.trim_start().strip_prefix(name))
+                .is_some_and(|rest| rest.starts_with('('))
+        })
+    };
+    let renamed: Vec<&str> = RENAMED_MACROS.into_iter().filter(|name| defines(name)).collect();
+    if renamed.is_empty() {
+        return code.to_owned();
+    }
+
+    let is_word = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut out = String::with_capacity(code.len() + 16);
+    let mut rest = code;
+    while let Some(start) = rest.find(|c: char| is_word(c)) {
+        let end = rest[start..].find(|c: char| !is_word(c)).map_or(rest.len(), |n| start + n);
+        let word = &rest[start..end];
+        out.push_str(&rest[..end]);
+        let after_dot = rest[..start].ends_with('.');
+        if renamed.contains(&word) && !after_dot && rest[end..].starts_with('(') {
+            out.push('x');
+        }
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// A pass's code wrapped into a complete fragment shader in one mode.
+#[derive(Clone, Debug)]
+pub struct WrappedSource {
+    pub mode: ShaderMode,
+    pub source: String,
+    /// Number of wrapper lines before the snippet.
+    pub header_lines: usize,
+    /// Snippet line of each line of `source`, `None` for wrapper lines.
+    pub lines: Vec<Option<usize>>,
+}
+
+impl WrappedSource {
+    fn new(
+        mode: ShaderMode,
+        header: &str,
+        snippet: &MappedSource,
+        body: &str,
+        footer: &str,
+    ) -> Self {
+        let header_lines = header.matches('\n').count();
+        let footer_lines = footer.matches('\n').count();
+        let mut lines = vec![None; header_lines];
+        lines.extend(snippet.lines.iter().map(|&line| Some(line)));
+        lines.extend(std::iter::repeat_n(None, footer_lines));
+        Self {
+            mode,
+            source: format!("{header}{body}{footer}"),
+            header_lines,
+            lines,
+        }
+    }
+
+    /// Snippet line of line `line` (1-based, as in compiler logs) of the
+    /// source, or `None` for the wrapper's own code.
+    pub fn snippet_line(&self, line: usize) -> Option<usize> {
+        self.lines.get(line.checked_sub(1)?).copied().flatten()
+    }
+}
+
+/// A pass's code wrapped for every mode it may be in.
+#[derive(Clone, Debug)]
+pub struct WrappedPass {
+    pub detection: Detection,
+    /// One source per mode, in the order of `detection`.
+    pub sources: Vec<WrappedSource>,
+}
+
+/// Wraps one pass's code for every mode it may be in, the likeliest first.
+/// Every variant is a header, the snippet starting on a line of its own, and
+/// a footer, so compiler line numbers can be mapped back. `declarations` are
+/// whole lines added to the tweet and Shadertoy headers, such as the
+/// uniforms of an embedding app; full shaders declare their own.
+pub fn wrap_pass(
+    snippet: &MappedSource,
+    forced: Option<ShaderMode>,
+    target: GlslTarget,
+    declarations: &str,
+) -> WrappedPass {
+    let detection = detect_mode(&snippet.text, forced);
+    let sources = detection
+        .order()
+        .iter()
+        .map(|&mode| match mode {
+            ShaderMode::Tweet => {
+                let header = tweet_header(target, declarations);
+                WrappedSource::new(mode, &header, snippet, &snippet.text, TWEET_FOOTER)
+            }
+            ShaderMode::Shadertoy => {
+                let header = shadertoy_header(target, declarations);
+                let body = rename_color_macros(&snippet.text);
+                WrappedSource::new(mode, &header, snippet, &body, "")
+            }
+            ShaderMode::Full => {
+                let header = full_header(&snippet.text, target);
+                WrappedSource::new(mode, &header, snippet, &snippet.text, "")
+            }
+        })
+        .collect();
+    WrappedPass { detection, sources }
+}
+
+/// Tweet-style body that writes to `o` and uses FC, r, t.
+fn tweet_header(target: GlslTarget, declarations: &str) -> String {
+    let GlslTarget { version, precision } = target;
+    format!(
+        r#"{version}
+            {precision}
+{declarations}            uniform vec2 r;
+            uniform float t;
+            uniform vec2 m;
+            uniform vec2 rect_min;
+            out vec4 fragColor;
+
+            void main() {{
+                vec2 FC = gl_FragCoord.xy - rect_min;
+                vec4 o = vec4(0.0);
+"#
+    )
+}
+
+const TWEET_FOOTER: &str = r#"
+                fragColor = o;
+            }
+        "#;
+
+/// Shadertoy's uniforms around a `mainImage` function.
+fn shadertoy_header(target: GlslTarget, declarations: &str) -> String {
+    let body = r#"
+            uniform float iTime;
+            uniform float iTimeDelta;
+            uniform float iFrameRate;
+            uniform vec3 iResolution;
+            uniform vec4 iMouse;
+            uniform int iFrame;
+            uniform vec4 iDate;
+            uniform float iSampleRate;
+            uniform float iChannelTime[4];
+            uniform vec3 iChannelResolution[4];
+            uniform sampler2D iChannel0;
+            uniform sampler2D iChannel1;
+            uniform sampler2D iChannel2;
+            uniform sampler2D iChannel3;
+            uniform vec2 rect_min;
+            out vec4 fragColor;
+
+            // Compatibility shim: some Shadertoy shaders sample 3D/4D coords
+            // even when the channel is a 2D sampler. Drop extra components so they
+            // still compile. Declaring `texture` overloads directly would hide
+            // the builtin ones, so route every call through shadyTexture.
+            vec4 shadyTexture(sampler2D tex, vec2 uv) { return texture(tex, uv); }
+            vec4 shadyTexture(sampler2D tex, vec2 uv, float bias) { return texture(tex, uv, bias); }
+            vec4 shadyTexture(sampler2D tex, vec3 uvw) { return textureLod(tex, uvw.xy, 0.0); }
+            vec4 shadyTexture(sampler2D tex, vec4 uvw) { return textureLod(tex, uvw.xy, 0.0); }
+            #define texture shadyTexture
+
+            void mainImage(out vec4 fragColor, in vec2 fragCoord);
+
+            void main() {
+                vec4 color = vec4(0.0);
+                mainImage(color, gl_FragCoord.xy - rect_min);
+                fragColor = color;
+            }
+        "#;
+    format!("{}\n{}\n{declarations}{body}\n", target.version, target.precision)
+}
+
+/// A complete shader is compiled as-is, with a `#version` line if it has
+/// none.
+fn full_header(code: &str, target: GlslTarget) -> String {
+    if has_version_line(code) {
+        String::new()
+    } else if target.precision.is_empty() {
+        format!("{}\n", target.version)
+    } else {
+        format!("{}\n{}\n", target.version, target.precision)
+    }
+}
+
+/// Wraps a Sound pass so that every pixel of a target `block_width` pixels
+/// wide evaluates `mainSound` for one sample, starting at `iSampleOffset`.
+/// Sound passes are always Shadertoy code.
+pub fn wrap_sound(snippet: &MappedSource, target: GlslTarget, block_width: i32) -> WrappedSource {
+    let GlslTarget { version, precision } = target;
+    let header = format!(
+        r#"{version}
+            {precision}
+            uniform float iSampleRate;
+            uniform int iSampleOffset;
+            out vec4 fragColor;
+
+            vec2 mainSound(int samp, float time);
+
+            void main() {{
+                ivec2 p = ivec2(gl_FragCoord.xy);
+                int samp = iSampleOffset + p.y * {block_width} + p.x;
+                vec2 s = mainSound(samp, float(samp) / iSampleRate);
+                fragColor = vec4(s, 0.0, 1.0);
+            }}
+"#
+    );
+    WrappedSource::new(ShaderMode::Shadertoy, &header, snippet, &snippet.text, "")
+}
+
+/// Vertex shader of every pass: one triangle covering the viewport.
+pub fn vertex_source(target: GlslTarget) -> String {
+    format!(
+        "{}\n{}",
+        target.version,
+        r#"
+            const vec2 verts[3] = vec2[3](
+                vec2(-1.0, -1.0),
+                vec2(3.0, -1.0),
+                vec2(-1.0, 3.0)
+            );
+
+            void main() {
+                gl_Position = vec4(verts[gl_VertexID], 0.0, 1.0);
+            }
+        "#
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A whole snippet, each line mapped to itself.
+    fn mapped(snippet: &str) -> MappedSource {
+        let mut source = MappedSource::default();
+        for (index, line) in snippet.lines().enumerate() {
+            source.push_line(line, index + 1);
+        }
+        source
+    }
+
+    fn wrap(snippet: &str) -> WrappedPass {
+        wrap_pass(&mapped(snippet), None, GlslTarget::DESKTOP, "")
+    }
+
+    fn modes(pass: &WrappedPass) -> Vec<ShaderMode> {
+        pass.sources.iter().map(|source| source.mode).collect()
+    }
+
+    #[test]
+    fn tweet_is_the_default() {
+        let pass = wrap("o = vec4(FC / r, 0.5 + 0.5 * sin(t), 1);");
+        assert_eq!(pass.detection, Detection::Tweet);
+        assert_eq!(
+            modes(&pass),
+            [ShaderMode::Tweet, ShaderMode::Shadertoy, ShaderMode::Full]
+        );
+    }
+
+    #[test]
+    fn detects_shadertoy_before_full() {
+        let pass = wrap("void mainImage(out vec4 c, in vec2 p) { c = vec4(iTime); }");
+        assert_eq!(pass.detection, Detection::Shadertoy("mainImage"));
+        assert_eq!(pass.sources[0].mode, ShaderMode::Shadertoy);
+    }
+
+    #[test]
+    fn detects_full_shaders() {
+        let detect = |snippet| detect_mode(snippet, None);
+        assert_eq!(detect("out vec4 c;\nvoid main() {}"), Detection::Full("void main"));
+        assert_eq!(detect("void f() { gl_FragColor = vec4(1); }"), Detection::Full("gl_FragColor"));
+        assert_eq!(detect("#version 330 core\nout vec4 c;"), Detection::Full("#version"));
+    }
+
+    #[test]
+    fn forced_mode_is_the_only_one() {
+        let snippet = mapped("void mainImage(out vec4 c, in vec2 p) {}");
+        let pass = wrap_pass(&snippet, Some(ShaderMode::Full), GlslTarget::DESKTOP, "");
+        assert_eq!(pass.detection, Detection::Forced(ShaderMode::Full));
+        assert_eq!(modes(&pass), [ShaderMode::Full]);
+    }
+
+    #[test]
+    fn comments_dont_count_for_detection() {
+        let pass = wrap("// port of a mainImage shader using iTime\no = vec4(t);");
+        assert_eq!(pass.detection, Detection::Tweet);
+        let pass = wrap("/* void main() {\n   #version 330 */\no = vec4(1);");
+        assert_eq!(pass.detection, Detection::Tweet);
+        let pass = wrap("float x = 1.0; // iTime\nvoid main() {}");
+        assert_eq!(pass.detection, Detection::Full("void main"));
+    }
+
+    #[test]
+    fn defines_count_for_detection() {
+        let pass = wrap("#define T iTime\no = vec4(sin(T));");
+        assert_eq!(pass.detection, Detection::Shadertoy("iTime"));
+    }
+
+    #[test]
+    fn strip_comments_keeps_lines() {
+        let code = "a // b\n/* c\nd */ e\nf/**/g";
+        let stripped = strip_comments(code);
+        assert_eq!(stripped.lines().count(), code.lines().count());
+        assert_eq!(stripped.split_whitespace().collect::<Vec<_>>(), ["a", "e", "f", "g"]);
+    }
+
+    #[test]
+    fn full_mode_keeps_the_snippets_version() {
+        let pass = wrap("#version 330 core\nout vec4 c;\nvoid main() { c = vec4(1); }");
+        let full = &pass.sources[0];
+        assert_eq!(full.mode, ShaderMode::Full);
+        assert_eq!(full.header_lines, 0);
+        assert!(full.source.starts_with("#version 330 core\n"));
+        assert_eq!(full.source.matches("#version").count(), 1);
+    }
+
+    #[test]
+    fn full_mode_adds_a_version() {
+        let pass = wrap("out vec4 c;\nvoid main() { c = vec4(1); }");
+        let full = &pass.sources[0];
+        assert_eq!(full.header_lines, 1);
+        assert!(full.source.starts_with("#version 330 core\nout vec4 c;"));
+
+        // A `#version` in a comment isn't one.
+        let pass = wrap("// needs #version 330\nvoid main() {}");
+        assert!(pass.sources[0].source.starts_with("#version 330 core\n//"));
+    }
+
+    #[test]
+    fn web_target_adds_precision() {
+        let snippet = mapped("void main() {}");
+        let pass = wrap_pass(&snippet, Some(ShaderMode::Full), GlslTarget::WEB, "");
+        let full = &pass.sources[0];
+        assert_eq!(full.header_lines, 2);
+        assert!(full.source.starts_with("#version 300 es\nprecision mediump float;\n"));
+    }
+
+    #[test]
+    fn line_maps_point_into_the_snippet() {
+        let pass = wrap("float a = 1.0;\nfloat b = 2.0;\no = vec4(a, b, 0, 1);");
+        for source in &pass.sources {
+            let first = source.header_lines + 1;
+            assert_eq!(source.snippet_line(first), Some(1), "{:?}", source.mode);
+            assert_eq!(source.snippet_line(first + 2), Some(3), "{:?}", source.mode);
+            assert_eq!(source.snippet_line(first + 3), None, "{:?}", source.mode);
+            assert_eq!(source.snippet_line(0), None);
+            if source.header_lines > 0 {
+                assert_eq!(source.snippet_line(source.header_lines), None);
+            }
+            let lines: Vec<&str> = source.source.lines().collect();
+            assert_eq!(lines[first - 1], "float a = 1.0;", "{:?}", source.mode);
+        }
+    }
+
+    #[test]
+    fn line_maps_follow_the_mapped_source() {
+        // Lines of a multipass file: common code on line 2, the pass on 10.
+        let mut snippet = MappedSource::default();
+        snippet.push_line("float common_value = 1.0;", 2);
+        snippet.push_line("o = vec4(common_value);", 10);
+        let pass = wrap_pass(&snippet, Some(ShaderMode::Tweet), GlslTarget::DESKTOP, "");
+        let tweet = &pass.sources[0];
+        assert_eq!(tweet.snippet_line(tweet.header_lines + 1), Some(2));
+        assert_eq!(tweet.snippet_line(tweet.header_lines + 2), Some(10));
+    }
+
+    #[test]
+    fn declarations_go_into_wrapped_headers() {
+        let declarations = "uniform float gain;\nuniform vec3 tint;\n";
+        let snippet = mapped("o = vec4(tint * gain, 1);");
+        let plain = wrap_pass(&snippet, None, GlslTarget::DESKTOP, "");
+        let pass = wrap_pass(&snippet, None, GlslTarget::DESKTOP, declarations);
+        for (plain, source) in plain.sources.iter().zip(&pass.sources) {
+            if source.mode == ShaderMode::Full {
+                assert_eq!(source.source, plain.source);
+                continue;
+            }
+            assert!(source.source.contains(declarations), "{:?}", source.mode);
+            assert_eq!(source.header_lines, plain.header_lines + 2, "{:?}", source.mode);
+            assert_eq!(source.snippet_line(source.header_lines + 1), Some(1));
+        }
+    }
+
+    #[test]
+    fn renames_defined_color_macros() {
+        let code = "#define rgb(r, g, b) vec3(r, g, b) / 255.0\nvec3 c = rgb(255, 0, 0);\nc.rgb;";
+        assert_eq!(
+            rename_color_macros(code),
+            "#define rgbx(r, g, b) vec3(r, g, b) / 255.0\nvec3 c = rgbx(255, 0, 0);\nc.rgb;"
+        );
+        let code = "# define grgb(x) vec3(x)\nvec3 c = grgb(1.0) + rgb(2.0);";
+        assert_eq!(
+            rename_color_macros(code),
+            "# define grgbx(x) vec3(x)\nvec3 c = grgbx(1.0) + rgb(2.0);"
+        );
+    }
+
+    #[test]
+    fn leaves_other_rgb_names_alone() {
+        // No macro: a function called rgb keeps its name.
+        let code = "vec3 rgb(float x) { return vec3(x); }\nvec3 c = rgb(1.0);";
+        assert_eq!(rename_color_macros(code), code);
+        let code = "#define rgb(x) vec3(x)\nvec3 c = hsv2rgb(v) + rgb(1.0);";
+        assert_eq!(
+            rename_color_macros(code),
+            "#define rgbx(x) vec3(x)\nvec3 c = hsv2rgb(v) + rgbx(1.0);"
+        );
+        // An object-like macro isn't renamed.
+        let code = "#define rgb vec3(1)\nvec3 c = rgb;";
+        assert_eq!(rename_color_macros(code), code);
+    }
+
+    #[test]
+    fn only_shadertoy_mode_renames_macros() {
+        let pass = wrap(
+            "#define rgb(x) vec3(x)\nvoid mainImage(out vec4 c, in vec2 p) { c.rgb = rgb(1.0); }",
+        );
+        for source in &pass.sources {
+            let renamed = source.source.contains("rgbx(");
+            assert_eq!(renamed, source.mode == ShaderMode::Shadertoy, "{:?}", source.mode);
+        }
+    }
+
+    #[test]
+    fn sound_pass_maps_lines() {
+        let snippet = mapped("vec2 mainSound(int s, float t) {\n    return vec2(0);\n}");
+        let sound = wrap_sound(&snippet, GlslTarget::DESKTOP, 512);
+        assert_eq!(sound.mode, ShaderMode::Shadertoy);
+        assert!(sound.source.contains("p.y * 512 + p.x"));
+        assert_eq!(sound.snippet_line(sound.header_lines + 2), Some(2));
+    }
+
+    #[test]
+    fn detection_explains_itself() {
+        assert_eq!(
+            Detection::Shadertoy("iTime").to_string(),
+            "Looks like Shadertoy code: uses `iTime`"
+        );
+        assert_eq!(Detection::Forced(ShaderMode::Full).to_string(), "Full GLSL mode is forced");
+    }
+}
@@ -0,0 +1,221 @@
+//! Golden-image tests: every shader in `tests/golden` is rendered headless on
+//! llvmpipe and compared with its reference PNGs. Each fixture is compiled
+//! once and rendered at all its times, so renders must not leak into each
+//! other.
+//!
+//! A fixture renders at 64×64 at 0 and 1 seconds unless a `// golden:` line
+//! says otherwise, e.g. `// golden: size=96x48 times=0,0.5,2`. Its references
+//! sit next to it as `<name>@<time>s.png`. Run with `SHADY_BLESS=1` to write
+//! missing or changed references instead of failing.
+//!
+//! Needs an EGL software context, so only runs on Linux, and is skipped
+//! where Mesa's llvmpipe isn't installed.
+
+#![cfg(target_os = "linux")]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use eframe::glow;
+
+use shady::{write_png, HeadlessGl, ShaderOptions, ShaderState};
+
+const FIXTURES_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden");
+/// Where the rendered and diff images of failing comparisons go.
+const OUTPUT_DIR: &str = concat!(env!("CARGO_TARGET_TMPDIR"), "/golden");
+const DEFAULT_SIZE: [u32; 2] = [64, 64];
+const DEFAULT_TIMES: &[f32] = &[0.0, 1.0];
+const FPS: u32 = 30;
+/// Largest difference of one channel that still counts as equal.
+const CHANNEL_TOLERANCE: u8 = 3;
+/// Share of pixels allowed to differ by more than the tolerance.
+const MAX_DIFFERING: f64 = 0.001;
+
+/// Rendering settings from a fixture's `// golden:` line.
+fn fixture_settings(source: &str) -> Result<([u32; 2], Vec<f32>), String> {
+    let mut size = DEFAULT_SIZE;
+    let mut times = DEFAULT_TIMES.to_vec();
+    let Some(line) = source.lines().find_map(|l| l.trim().strip_prefix("// golden:")) else {
+        return Ok((size, times));
+    };
+    for word in line.split_whitespace() {
+        match word.split_once('=') {
+            Some(("size", value)) => {
+                size = value
+                    .split_once('x')
+                    .and_then(|(w, h)| Some([w.parse().ok()?, h.parse().ok()?]))
+                    .ok_or_else(|| format!("invalid size `{value}`"))?;
+            }
+            Some(("times", value)) => {
+                times = value
+                    .split(',')
+                    .map(|t| t.parse().map_err(|_| format!("invalid time `{t}`")))
+                    .collect::<Result<_, _>>()?;
+            }
+            _ => return Err(format!("unknown golden setting `{word}`")),
+        }
+    }
+    Ok((size, times))
+}
+
+/// Compares two RGBA images. Returns the share of pixels that differ by more
+/// than the tolerance and a diff image: those pixels in red over a dimmed
+/// grayscale copy of `expected`.
+fn compare(expected: &[u8], actual: &[u8]) -> (f64, Vec<u8>) {
+    let mut differing = 0;
+    let mut diff = Vec::with_capacity(expected.len());
+    for (e, a) in expected.chunks_exact(4).zip(actual.chunks_exact(4)) {
+        if e.iter().zip(a).any(|(e, a)| e.abs_diff(*a) > CHANNEL_TOLERANCE) {
+            differing += 1;
+            diff.extend_from_slice(&[255, 0, 0, 255]);
+        } else {
+            let gray = ((u32::from(e[0]) + u32::from(e[1]) + u32::from(e[2])) / 9) as u8;
+            diff.extend_from_slice(&[gray, gray, gray, 255]);
+        }
+    }
+    (f64::from(differing) / (expected.len() / 4) as f64, diff)
+}
+
+fn read_png(path: &Path) -> Result<([u32; 2], Vec<u8>), String> {
+    let image = image::open(path).map_err(|e| format!("cannot read {}: {e}", path.display()))?;
+    let image = image.to_rgba8();
+    Ok(([image.width(), image.height()], image.into_raw()))
+}
+
+/// Renders one fixture at each of its times and checks the results. Returns
+/// a message per failed comparison.
+fn check_fixture(gl: &glow::Context, path: &Path, bless: bool) -> Vec<String> {
+    let name = path.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) => return vec![format!("{name}: cannot read fixture: {e}")],
+    };
+    let (size, times) = match fixture_settings(&source) {
+        Ok(settings) => settings,
+        Err(e) => return vec![format!("{name}: {e}")],
+    };
+    let options = ShaderOptions {
+        base_dir: path.parent().map(Path::to_path_buf),
+        ..Default::default()
+    };
+
+    let mut shader = match ShaderState::new(gl, &source, &options) {
+        Ok(shader) => shader,
+        Err(e) => return vec![format!("{name}: {e}")],
+    };
+    let mut failures = Vec::new();
+    for time in times {
+        let image_name = format!("{name}@{time}s.png");
+        let actual = match shader.render_frame(gl, time, FPS, size) {
+            Ok(actual) => actual,
+            Err(e) => {
+                failures.push(format!("{image_name}: {e}"));
+                continue;
+            }
+        };
+
+        let reference = path.with_file_name(&image_name);
+        let expected = match read_png(&reference) {
+            Ok((expected_size, expected)) if expected_size == size => Some(expected),
+            _ => None,
+        };
+        let differing = match &expected {
+            Some(expected) => {
+                let (differing, diff) = compare(expected, &actual);
+                if differing <= MAX_DIFFERING {
+                    continue;
+                }
+                if !bless {
+                    let output = Path::new(OUTPUT_DIR);
+                    let diff_name = format!("{name}@{time}s.diff.png");
+                    let written = fs::create_dir_all(output)
+                        .map_err(|e| e.to_string())
+                        .and_then(|()| write_png(&output.join(&image_name), &actual, size))
+                        .and_then(|()| write_png(&output.join(diff_name), &diff, size));
+                    if let Err(e) = written {
+                        failures.push(format!("{image_name}: cannot write the diff: {e}"));
+                    }
+                }
+                Some(differing)
+            }
+            None => None,
+        };
+
+        if bless {
+            if let Err(e) = write_png(&reference, &actual, size) {
+                failures.push(format!("{image_name}: {e}"));
+            }
+        } else if let Some(differing) = differing {
+            failures.push(format!(
+                "{image_name}: {:.2}% of pixels differ, see {OUTPUT_DIR}",
+                differing * 100.0
+            ));
+        } else {
+            failures.push(format!("{image_name}: no reference of size {}x{}", size[0], size[1]));
+        }
+    }
+    shader.destroy(gl);
+    failures
+}
+
+#[test]
+fn golden_images() {
+    let headless = match HeadlessGl::software() {
+        Ok(headless) => headless,
+        Err(e) => {
+            eprintln!("skipping golden images: no llvmpipe EGL context ({e})");
+            return;
+        }
+    };
+    let bless = std::env::var_os("SHADY_BLESS").is_some_and(|v| v != "0");
+
+    let mut fixtures: Vec<PathBuf> = fs::read_dir(FIXTURES_DIR)
+        .expect("cannot read tests/golden")
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|e| e == "glsl"))
+        .collect();
+    fixtures.sort();
+    assert!(!fixtures.is_empty(), "no fixtures in tests/golden");
+
+    let failures: Vec<String> = fixtures
+        .iter()
+        .flat_map(|path| check_fixture(&headless.gl, path, bless))
+        .collect();
+    assert!(
+        failures.is_empty(),
+        "{} golden image(s) failed (SHADY_BLESS=1 updates the references):\n{}",
+        failures.len(),
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn render_frame_starts_from_empty_buffers() {
+    let Ok(headless) = HeadlessGl::software() else {
+        eprintln!("skipping render_frame test: no llvmpipe EGL context");
+        return;
+    };
+    // Buffer A counts the frames it ran.
+    let source = "\
+// @shady pass: Buffer A
+// @shady iChannel0: self
+void mainImage(out vec4 fragColor, in vec2 fragCoord) {
+    fragColor = texture(iChannel0, fragCoord / iResolution.xy) + 1.0 / 64.0;
+}
+
+// @shady pass: Image
+// @shady iChannel0: buffer Buffer A
+void mainImage(out vec4 fragColor, in vec2 fragCoord) {
+    fragColor = texture(iChannel0, fragCoord / iResolution.xy);
+}
+";
+    let gl = &headless.gl;
+    let mut shader = ShaderState::new(gl, source, &ShaderOptions::default()).unwrap();
+    let first = shader.render_frame(gl, 0.1, FPS, [8, 8]);
+    let second = shader.render_frame(gl, 0.1, FPS, [8, 8]);
+    shader.destroy(gl);
+    let (first, second) = (first.unwrap(), second.unwrap());
+    // Frames 0 to 3 ran, each adding 1/64.
+    assert_eq!(first[0], 16);
+    assert_eq!(first, second);
+}
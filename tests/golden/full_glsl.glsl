@@ -0,0 +1,9 @@
+// golden: times=0
+#version 330 core
+out vec4 color;
+
+void main() {
+    vec2 cell = floor(gl_FragCoord.xy / 8.0);
+    float checker = mod(cell.x + cell.y, 2.0);
+    color = vec4(vec3(0.2 + 0.6 * checker), 1.0);
+}
@@ -0,0 +1,17 @@
+// golden: times=0,0.5
+// @shady pass: Buffer A
+// @shady iChannel0: self
+void mainImage(out vec4 fragColor, in vec2 fragCoord) {
+    vec2 uv = fragCoord / iResolution.xy;
+    vec4 previous = texture(iChannel0, uv);
+    vec2 dot = vec2(0.5) + 0.35 * vec2(cos(3.0 * iTime), sin(3.0 * iTime));
+    float spot = smoothstep(0.08, 0.04, length(uv - dot));
+    fragColor = max(previous * 0.95, vec4(spot));
+}
+
+// @shady pass: Image
+// @shady iChannel0: buffer Buffer A
+void mainImage(out vec4 fragColor, in vec2 fragCoord) {
+    float trail = texture(iChannel0, fragCoord / iResolution.xy).r;
+    fragColor = vec4(trail, 0.5 * trail, 1.0 - trail, 1.0);
+}
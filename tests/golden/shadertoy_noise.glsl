@@ -0,0 +1,7 @@
+// golden: times=0
+// iChannel0 is the built-in noise texture.
+void mainImage(out vec4 fragColor, in vec2 fragCoord) {
+    vec2 uv = fragCoord / iResolution.xy;
+    float n = texture(iChannel0, uv * 0.5).r;
+    fragColor = vec4(vec3(n) * vec3(uv, 1.0), 1.0);
+}
@@ -0,0 +1,7 @@
+// golden: size=96x48 times=0,0.5,2
+void mainImage(out vec4 fragColor, in vec2 fragCoord) {
+    vec2 uv = (2.0 * fragCoord - iResolution.xy) / iResolution.y;
+    float d = length(uv);
+    float rings = smoothstep(0.0, 0.1, abs(sin(10.0 * d - 3.0 * iTime)) - 0.3);
+    fragColor = vec4(rings * vec3(0.9, 0.6, 0.2) + 0.1 * uv.x, 1.0);
+}
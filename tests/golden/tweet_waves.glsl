@@ -0,0 +1,17 @@
+// The editor's default snippet.
+vec2 uv = FC.xy / r.xy;
+uv.x *= r.x / r.y;
+
+float time = t * 0.5;
+
+for (float i = 1.0; i < 4.0; i++) {
+    uv.x += 0.6 / i * cos(i * 2.5 * uv.y + time);
+    uv.y += 0.6 / i * cos(i * 1.5 * uv.x + time);
+}
+
+vec3 color = vec3(0.0);
+color.r = 0.5 + 0.5 * sin(uv.x + time);
+color.g = 0.5 + 0.5 * sin(uv.y + time + 2.0);
+color.b = 0.5 + 0.5 * sin(uv.x + uv.y + time + 4.0);
+
+o = vec4(color, 1.0);